@@ -1,9 +1,6 @@
-use rand::{Rng, SeedableRng};
+use crate::{filter, hittable, image, integrator, progress, ray, sampler, stats, tile, vec3};
 
-use crate::{
-    consts, hittable, lerp, ray,
-    vec3::{self, Vec3},
-};
+const STOPPABLE_PASS_SAMPLES: u32 = 4;
 
 pub struct Camera {
     pub aspect_ratio: f64,
@@ -13,41 +10,377 @@ pub struct Camera {
     pixel_0_loc: vec3::Point3,
     pixel_delta_u: vec3::Vec3,
     pixel_delta_v: vec3::Vec3,
+    // Upper bound of samples per pixel when adaptive sampling is enabled
     pub samples_per_pixel: u32,
-    pub max_bounces: u32,
+    pub min_samples_per_pixel: u32,
+    // Pixels whose estimated noise falls below this stop being sampled, 0 disables adaptive sampling
+    pub noise_threshold: f64,
+    pub sample_heatmap_path: Option<std::path::PathBuf>,
+    pub integrator: Box<dyn integrator::Integrator>,
+    pub sampler: Box<dyn sampler::Sampler>,
+    pub filter: Box<dyn filter::Filter>,
+    pub tile_size: usize,
+    pub tile_order: tile::TileOrder,
+    // Only the pixels inside the window are traced, the whole image when unset
+    pub crop_window: Option<tile::CropWindow>,
+    // The render is saved here every `checkpoint_interval` and once it's done
+    pub checkpoint_path: Option<std::path::PathBuf>,
+    pub checkpoint_interval: std::time::Duration,
+    // Continue from the checkpoint at `checkpoint_path` instead of starting over
+    pub resume: bool,
+    // Identifies the scene, checkpoints of other scenes are never resumed
+    pub scene_hash: u64,
+    last_checkpoint: std::time::Instant,
+    // Rendering stops once either runs out, keeping the samples traced so far.
+    // Progressive renders make the most of this since every pass covers the
+    // whole image.
+    pub time_budget: Option<std::time::Duration>,
+    pub cancel_token: Option<CancelToken>,
+    deadline: Option<std::time::Instant>,
+    // Silent by default, `progress::ConsoleProgress` prints warnings to stderr
+    pub progress: Box<dyn progress::ProgressObserver>,
+    // Count rays, intersection tests and path terminations, handed over with
+    // the final render stats
+    pub collect_statistics: bool,
+    state: RenderState,
+}
+
+// Bookkeeping of the render in flight, reset by `initialize`
+struct RenderState {
+    start: std::time::Instant,
+    completed_units: u64,
+    total_units: u64,
+    camera_rays: u64,
+    stopped_early: bool,
+}
+
+impl RenderState {
+    fn new() -> Self {
+        Self {
+            start: std::time::Instant::now(),
+            completed_units: 0,
+            total_units: 0,
+            camera_rays: 0,
+            stopped_early: false,
+        }
+    }
+}
+
+// Shared flag to stop a render from another thread
+#[derive(Clone, Default)]
+pub struct CancelToken {
+    cancelled: std::sync::Arc<std::sync::atomic::AtomicBool>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled
+            .store(true, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(std::sync::atomic::Ordering::Relaxed)
+    }
 }
 
 impl Camera {
-    pub fn render(&mut self, world: &hittable::HittableObjects) {
-        let samples_per_pixel = self.samples_per_pixel as f64;
-        let mut rng_gen = rand::rngs::SmallRng::from_entropy();
-        use std::io::Write;
+    pub fn render(
+        &mut self,
+        world: &hittable::HittableObjects,
+        lights: &hittable::HittableObjects,
+    ) {
+        self.initialize();
+        let mut buffer = self.start_buffer();
+        self.render_region(&mut buffer, world, lights);
+        self.write_checkpoint(&buffer);
+        self.finish();
+
+        // A crop window produces an image of just the window
+        let region = self.region();
+        if self.crop_window.is_some() {
+            buffer = buffer.crop(&region);
+        }
         let mut write_buffer = std::io::BufWriter::new(std::io::stdout());
+        buffer.write_ppm(&mut write_buffer).unwrap();
+        self.write_sample_heatmap(&buffer);
+    }
+
+    // Rerenders the crop window (or the whole image) on top of an existing
+    // image of the same size, leaving the pixels outside the window untouched.
+    // Pixels a stopped render didn't get to keep their previous color.
+    pub fn render_into(
+        &mut self,
+        buffer: &mut image::AccumulationBuffer,
+        world: &hittable::HittableObjects,
+        lights: &hittable::HittableObjects,
+    ) {
+        self.initialize();
+        assert!(
+            buffer.width() == self.img_width && buffer.height() == self.img_height,
+            "Buffer is {} x {} but the camera renders {} x {}",
+            buffer.width(),
+            buffer.height(),
+            self.img_width,
+            self.img_height
+        );
+        let mut rendered = image::AccumulationBuffer::new(self.img_width, self.img_height);
+        self.render_region(&mut rendered, world, lights);
+        buffer.replace_sampled(&rendered, &self.region());
+        self.finish();
+        self.write_sample_heatmap(buffer);
+    }
+
+    fn render_region(
+        &mut self,
+        buffer: &mut image::AccumulationBuffer,
+        world: &hittable::HittableObjects,
+        lights: &hittable::HittableObjects,
+    ) {
+        // A render that may stop early is refined over the whole region in
+        // passes, so stopping leaves it evenly sampled
+        let pass_samples = if self.time_budget.is_some() || self.cancel_token.is_some() {
+            STOPPABLE_PASS_SAMPLES
+        } else {
+            u32::max(self.samples_per_pixel, 1)
+        };
+        self.render_passes(buffer, pass_samples, world, lights, &mut |_, _| {});
+    }
+
+    fn render_passes(
+        &mut self,
+        buffer: &mut image::AccumulationBuffer,
+        pass_samples: u32,
+        world: &hittable::HittableObjects,
+        lights: &hittable::HittableObjects,
+        on_pass: &mut dyn FnMut(&image::AccumulationBuffer, u32),
+    ) {
+        let tiles = tile::tiles(&self.region(), self.tile_size, self.tile_order);
+        let passes = self.samples_per_pixel.div_ceil(pass_samples);
+        self.state.total_units = tiles.len() as u64 * passes as u64;
+
+        for pass in 0..passes {
+            let pass_target = u32::min((pass + 1) * pass_samples, self.samples_per_pixel);
+            for tile in &tiles {
+                if self.should_stop() {
+                    break;
+                }
+                self.render_tile(tile, buffer, pass_target, world, lights);
+                self.state.completed_units += 1;
+                self.progress.on_progress(&progress::Progress {
+                    completed_units: self.state.completed_units,
+                    total_units: self.state.total_units,
+                    elapsed: self.state.start.elapsed(),
+                    camera_rays: self.state.camera_rays,
+                });
+                self.checkpoint_if_due(buffer);
+            }
+            on_pass(buffer, pass);
+            if self.should_stop() {
+                self.state.stopped_early = true;
+                break;
+            }
+        }
+    }
+
+    fn finish(&mut self) {
+        self.progress.on_finish(&progress::RenderStats {
+            elapsed: self.state.start.elapsed(),
+            camera_rays: self.state.camera_rays,
+            stopped_early: self.state.stopped_early,
+            statistics: stats::take(),
+        });
+    }
+
+    fn should_stop(&self) -> bool {
+        self.deadline
+            .is_some_and(|deadline| std::time::Instant::now() >= deadline)
+            || self
+                .cancel_token
+                .as_ref()
+                .is_some_and(CancelToken::is_cancelled)
+    }
+
+    // The checkpoint to resume from if there is a matching one, an empty buffer otherwise
+    fn start_buffer(&mut self) -> image::AccumulationBuffer {
+        let fresh = image::AccumulationBuffer::new(self.img_width, self.img_height);
+        let Some(path) = self.checkpoint_path.as_ref().filter(|_| self.resume) else {
+            return fresh;
+        };
+
+        let result = std::fs::File::open(path).and_then(|file| {
+            image::AccumulationBuffer::read_checkpoint(
+                &mut std::io::BufReader::new(file),
+                self.img_width,
+                self.img_height,
+            )
+        });
+        match result {
+            Ok((buffer, header)) if header.scene_hash == self.scene_hash => {
+                self.progress
+                    .on_message(&format!("Resuming from {}", path.display()));
+                self.sampler.set_seed(header.sampler_seed);
+                buffer
+            }
+            Ok(_) => {
+                self.progress.on_warning(&format!(
+                    "Checkpoint {} is of a different render, starting over",
+                    path.display()
+                ));
+                fresh
+            }
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => fresh,
+            Err(error) => {
+                self.progress.on_warning(&format!(
+                    "Couldn't read checkpoint {}: {error}, starting over",
+                    path.display()
+                ));
+                fresh
+            }
+        }
+    }
+
+    fn checkpoint_if_due(&mut self, buffer: &image::AccumulationBuffer) {
+        if self.last_checkpoint.elapsed() >= self.checkpoint_interval {
+            self.write_checkpoint(buffer);
+        }
+    }
+
+    fn write_checkpoint(&mut self, buffer: &image::AccumulationBuffer) {
+        self.last_checkpoint = std::time::Instant::now();
+        let Some(path) = &self.checkpoint_path else {
+            return;
+        };
+        let header = image::CheckpointHeader {
+            sampler_seed: self.sampler.seed(),
+            scene_hash: self.scene_hash,
+        };
+        if let Err(error) = buffer.write_checkpoint_file(path, &header) {
+            self.progress.on_warning(&format!(
+                "Couldn't write checkpoint to {}: {error}",
+                path.display()
+            ));
+        }
+    }
+
+    // Pixels to trace, only valid after `initialize`
+    fn region(&self) -> tile::Tile {
+        match &self.crop_window {
+            Some(window) => window.to_pixels(self.img_width, self.img_height),
+            None => tile::Tile {
+                row_start: 0,
+                row_end: self.img_height,
+                column_start: 0,
+                column_end: self.img_width,
+            },
+        }
+    }
+
+    // Renders the image (or its crop window) in passes of `samples_per_pass` samples per pixel
+    // until `samples_per_pixel` is reached, calling `on_pass` with the image so
+    // far after every pass
+    pub fn render_progressive(
+        &mut self,
+        world: &hittable::HittableObjects,
+        lights: &hittable::HittableObjects,
+        samples_per_pass: u32,
+        mut on_pass: impl FnMut(&image::AccumulationBuffer, u32),
+    ) -> image::AccumulationBuffer {
+        debug_assert!(samples_per_pass >= 1, "Passes need at least one sample");
         self.initialize();
-        println!("P3");
-        println!("{} {}", self.img_width, self.img_height);
-        println!("255");
-        for i in 0..self.img_height {
-            eprint!("\rRemaining lines: {} ", self.img_height - i);
-            // img.color_codes.push(Vec::with_capacity(IMG_WIDTH));
-            for j in 0..self.img_width {
-                let mut pixel_color = vec3::Vec3::zeroed();
-                for _ in 0..self.samples_per_pixel {
-                    let ray = self.get_ray(i, j, &mut rng_gen);
-
-                    pixel_color += Self::ray_color(&ray, self.max_bounces, world, &mut rng_gen);
+        let mut buffer = self.start_buffer();
+        self.render_passes(&mut buffer, samples_per_pass, world, lights, &mut on_pass);
+        self.write_checkpoint(&buffer);
+        self.finish();
+        self.write_sample_heatmap(&buffer);
+
+        buffer
+    }
+
+    // Samples every pixel of the tile until it has `target_samples` samples
+    fn render_tile(
+        &mut self,
+        tile: &tile::Tile,
+        buffer: &mut image::AccumulationBuffer,
+        target_samples: u32,
+        world: &hittable::HittableObjects,
+        lights: &hittable::HittableObjects,
+    ) {
+        for i in tile.row_start..tile.row_end {
+            for j in tile.column_start..tile.column_end {
+                while buffer.sample_count(i, j) < target_samples
+                    && !self.pixel_converged(buffer, i, j)
+                {
+                    if self.should_stop() {
+                        return;
+                    }
+                    let (color, weight) = self.sample_pixel(i, j, buffer, world, lights);
+                    buffer.add_sample(i, j, color, weight);
                 }
-                pixel_color.write_color(&mut write_buffer, samples_per_pixel);
-                //  img.color_codes[i].push(pixel_color);
             }
         }
-        write_buffer.flush().unwrap();
+    }
+
+    // The noise estimate needs at least two samples, whatever `min_samples_per_pixel` says
+    fn pixel_converged(&self, buffer: &image::AccumulationBuffer, i: usize, j: usize) -> bool {
+        self.noise_threshold > 0.0
+            && buffer.sample_count(i, j) >= u32::max(self.min_samples_per_pixel, 2)
+            && buffer
+                .pixel_noise(i, j)
+                .is_some_and(|noise| noise < self.noise_threshold)
+    }
+
+    fn write_sample_heatmap(&mut self, buffer: &image::AccumulationBuffer) {
+        let Some(path) = &self.sample_heatmap_path else {
+            return;
+        };
+        let result = std::fs::File::create(path)
+            .and_then(|file| buffer.write_sample_heatmap(&mut std::io::BufWriter::new(file)));
+        if let Err(error) = result {
+            self.progress.on_warning(&format!(
+                "Couldn't write sample heatmap to {}: {error}",
+                path.display()
+            ));
+        }
+    }
+
+    // Traces one sample of the pixel, returning its color and filter weight
+    fn sample_pixel(
+        &mut self,
+        i: usize,
+        j: usize,
+        buffer: &image::AccumulationBuffer,
+        world: &hittable::HittableObjects,
+        lights: &hittable::HittableObjects,
+    ) -> (vec3::Color, f64) {
+        self.state.camera_rays += 1;
+        stats::record(|statistics| statistics.primary_rays += 1);
+        self.sampler
+            .start_pixel_sample(i, j, buffer.sample_count(i, j));
+        let filter_sample = self.filter.sample(self.sampler.get_pixel_2d());
+        let ray = self.get_ray(i, j, filter_sample.offset);
+
+        let color = self
+            .integrator
+            .ray_color(ray, world, lights, self.sampler.as_mut());
+        (color, filter_sample.weight)
     }
 
     fn initialize(&mut self) {
         self.img_height = (self.img_width as f64 / self.aspect_ratio) as usize;
         debug_assert!(self.img_height >= 1, "Image height must be at least 1");
-        eprintln!("Size: {} x {}", self.img_width, self.img_height);
+        self.state = RenderState::new();
+        if self.collect_statistics {
+            stats::enable();
+        }
+        self.progress.on_start(self.img_width, self.img_height);
+        self.last_checkpoint = std::time::Instant::now();
+        self.deadline = self
+            .time_budget
+            .map(|budget| std::time::Instant::now() + budget);
 
         const FOCAL_LENGTH: f64 = 1.0;
         const VIEWPORT_HEIGHT: f64 = 2.0;
@@ -76,62 +409,17 @@ impl Camera {
         let pixel_0_loc = viewport_upper_left + (0.5 * (&pixel_delta_u + &pixel_delta_v)); */
     }
 
-    fn ray_color(
-        ray: &ray::Ray,
-        depth: u32,
-        world: &hittable::HittableObjects,
-        rng_gen: &mut rand::rngs::SmallRng,
-    ) -> vec3::Color {
-        if depth == 0 {
-            return vec3::Color::zeroed();
-        }
-        let mut hit_record = hittable::HitRecord::new();
-        if world.hit(
-            ray,
-            consts::Interval::new(0.001, consts::INFINITY),
-            &mut hit_record,
-        ) {
-            let zero = Vec3::zeroed();
-            let mut scattered = ray::Ray::new(&zero, Vec3::zeroed());
-            let mut attenuation = vec3::Color::zeroed();
-            let material = hit_record.material.clone().unwrap();
-            if material.scatter(
-                ray,
-                &mut hit_record,
-                &mut attenuation,
-                &mut scattered,
-                rng_gen,
-            ) {
-                return attenuation * Self::ray_color(&scattered, depth - 1, world, rng_gen);
-            }
-            return vec3::Color::zeroed();
-        }
-        let unit_direction = ray.dir().unit_vector();
-        let a = 0.5 * (unit_direction.y() + 1.0); // Normalize values from -1 to 1 to 0 to 1
-
-        lerp(
-            a,
-            vec3::Color::new(1.0, 1.0, 1.0),
-            vec3::Color::new(0.5, 0.7, 1.0),
-        )
-    }
-
-    fn get_ray(&self, i: usize, j: usize, rng_gen: &mut rand::rngs::SmallRng) -> ray::Ray {
+    fn get_ray(&self, i: usize, j: usize, pixel_offset: (f64, f64)) -> ray::Ray {
         let pixel_center =
             (i as f64 * &self.pixel_delta_v) + (j as f64 * &self.pixel_delta_u) + &self.pixel_0_loc;
 
-        let pixel_sample = pixel_center + self.pixel_sample_square(rng_gen);
+        let pixel_sample = pixel_center
+            + (pixel_offset.0 * &self.pixel_delta_u)
+            + (pixel_offset.1 * &self.pixel_delta_v);
 
         let ray_dir = &pixel_sample - &self.center;
 
-        return ray::Ray::new(&self.center, ray_dir);
-    }
-
-    fn pixel_sample_square(&self, rng_gen: &mut rand::rngs::SmallRng) -> vec3::Vec3 {
-        let point_x: f64 = -0.5 + rng_gen.gen::<f64>();
-        let point_y: f64 = -0.5 + rng_gen.gen::<f64>();
-
-        (point_x * &self.pixel_delta_u) + (point_y * &self.pixel_delta_v)
+        ray::Ray::new(self.center.clone(), ray_dir)
     }
 }
 
@@ -146,7 +434,26 @@ impl Default for Camera {
             pixel_delta_u: vec3::Vec3::zeroed(),
             pixel_delta_v: vec3::Vec3::zeroed(),
             samples_per_pixel: 100,
-            max_bounces: 10,
+            min_samples_per_pixel: 16,
+            noise_threshold: 0.0,
+            sample_heatmap_path: None,
+            integrator: Box::new(integrator::PathIntegrator::default()),
+            sampler: Box::new(sampler::IndependentSampler::new(rand::random())),
+            filter: Box::new(filter::BoxFilter::default()),
+            tile_size: 32,
+            tile_order: tile::TileOrder::Spiral,
+            crop_window: None,
+            checkpoint_path: None,
+            checkpoint_interval: std::time::Duration::from_secs(300),
+            resume: false,
+            scene_hash: 0,
+            last_checkpoint: std::time::Instant::now(),
+            time_budget: None,
+            cancel_token: None,
+            deadline: None,
+            progress: Box::new(progress::SilentProgress),
+            collect_statistics: false,
+            state: RenderState::new(),
         }
     }
 }
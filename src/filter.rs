@@ -0,0 +1,372 @@
+use crate::consts;
+
+// Offset from the pixel center, in pixels, and the weight the traced sample
+// gets in that pixel's average
+pub struct FilterSample {
+    pub offset: (f64, f64),
+    pub weight: f64,
+}
+
+// Pixel reconstruction filter. Samples are importance sampled from the
+// filter (filter importance sampling), so every pixel is still estimated
+// independently as a weighted average of its own samples.
+pub trait Filter {
+    fn radius(&self) -> (f64, f64);
+    fn evaluate(&self, point: (f64, f64)) -> f64;
+    fn sample(&self, sample: (f64, f64)) -> FilterSample;
+}
+
+pub struct BoxFilter {
+    radius: (f64, f64),
+}
+
+impl BoxFilter {
+    pub fn new(radius: (f64, f64)) -> Self {
+        Self { radius }
+    }
+}
+
+impl Default for BoxFilter {
+    fn default() -> Self {
+        Self::new((0.5, 0.5))
+    }
+}
+
+impl Filter for BoxFilter {
+    fn radius(&self) -> (f64, f64) {
+        self.radius
+    }
+
+    fn evaluate(&self, point: (f64, f64)) -> f64 {
+        if f64::abs(point.0) <= self.radius.0 && f64::abs(point.1) <= self.radius.1 {
+            1.0
+        } else {
+            0.0
+        }
+    }
+
+    fn sample(&self, sample: (f64, f64)) -> FilterSample {
+        FilterSample {
+            offset: (
+                (2.0 * sample.0 - 1.0) * self.radius.0,
+                (2.0 * sample.1 - 1.0) * self.radius.1,
+            ),
+            weight: 1.0,
+        }
+    }
+}
+
+pub struct TentFilter {
+    radius: (f64, f64),
+}
+
+impl TentFilter {
+    pub fn new(radius: (f64, f64)) -> Self {
+        Self { radius }
+    }
+
+    fn sample_tent(sample: f64, radius: f64) -> f64 {
+        if sample < 0.5 {
+            -radius * (1.0 - f64::sqrt(1.0 - 2.0 * sample))
+        } else {
+            radius * (1.0 - f64::sqrt(1.0 - (2.0 * sample - 1.0)))
+        }
+    }
+}
+
+impl Default for TentFilter {
+    fn default() -> Self {
+        Self::new((1.0, 1.0))
+    }
+}
+
+impl Filter for TentFilter {
+    fn radius(&self) -> (f64, f64) {
+        self.radius
+    }
+
+    fn evaluate(&self, point: (f64, f64)) -> f64 {
+        f64::max(0.0, self.radius.0 - f64::abs(point.0))
+            * f64::max(0.0, self.radius.1 - f64::abs(point.1))
+    }
+
+    fn sample(&self, sample: (f64, f64)) -> FilterSample {
+        FilterSample {
+            offset: (
+                Self::sample_tent(sample.0, self.radius.0),
+                Self::sample_tent(sample.1, self.radius.1),
+            ),
+            weight: 1.0,
+        }
+    }
+}
+
+pub struct GaussianFilter {
+    radius: (f64, f64),
+    sigma: f64,
+    sampler: TabulatedFilterSampler,
+}
+
+impl GaussianFilter {
+    pub fn new(radius: (f64, f64), sigma: f64) -> Self {
+        let evaluate = |point: (f64, f64)| Self::gaussian_2d(point, radius, sigma);
+        Self {
+            radius,
+            sigma,
+            sampler: TabulatedFilterSampler::new(radius, evaluate),
+        }
+    }
+
+    fn gaussian(x: f64, sigma: f64) -> f64 {
+        f64::exp(-x * x / (2.0 * sigma * sigma)) / f64::sqrt(2.0 * consts::PI * sigma * sigma)
+    }
+
+    // Shifted down so the filter reaches zero at its radius
+    fn gaussian_2d(point: (f64, f64), radius: (f64, f64), sigma: f64) -> f64 {
+        f64::max(
+            0.0,
+            Self::gaussian(point.0, sigma) - Self::gaussian(radius.0, sigma),
+        ) * f64::max(
+            0.0,
+            Self::gaussian(point.1, sigma) - Self::gaussian(radius.1, sigma),
+        )
+    }
+}
+
+impl Default for GaussianFilter {
+    fn default() -> Self {
+        Self::new((1.5, 1.5), 0.5)
+    }
+}
+
+impl Filter for GaussianFilter {
+    fn radius(&self) -> (f64, f64) {
+        self.radius
+    }
+
+    fn evaluate(&self, point: (f64, f64)) -> f64 {
+        Self::gaussian_2d(point, self.radius, self.sigma)
+    }
+
+    fn sample(&self, sample: (f64, f64)) -> FilterSample {
+        self.sampler.sample(sample, |point| self.evaluate(point))
+    }
+}
+
+// Mitchell-Netravali cubic, with the default b = c = 1/3. Its negative lobes
+// sharpen edges, which shows up as samples with negative weights.
+pub struct MitchellFilter {
+    radius: (f64, f64),
+    b: f64,
+    c: f64,
+    sampler: TabulatedFilterSampler,
+}
+
+impl MitchellFilter {
+    pub fn new(radius: (f64, f64), b: f64, c: f64) -> Self {
+        let evaluate = |point: (f64, f64)| Self::mitchell_2d(point, radius, b, c);
+        Self {
+            radius,
+            b,
+            c,
+            sampler: TabulatedFilterSampler::new(radius, evaluate),
+        }
+    }
+
+    fn mitchell_1d(x: f64, b: f64, c: f64) -> f64 {
+        let x = f64::abs(x);
+        if x <= 1.0 {
+            ((12.0 - 9.0 * b - 6.0 * c) * x * x * x
+                + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+                + (6.0 - 2.0 * b))
+                / 6.0
+        } else if x <= 2.0 {
+            ((-b - 6.0 * c) * x * x * x
+                + (6.0 * b + 30.0 * c) * x * x
+                + (-12.0 * b - 48.0 * c) * x
+                + (8.0 * b + 24.0 * c))
+                / 6.0
+        } else {
+            0.0
+        }
+    }
+
+    // The 1D filter spans [-2, 2], scaled to the radius
+    fn mitchell_2d(point: (f64, f64), radius: (f64, f64), b: f64, c: f64) -> f64 {
+        Self::mitchell_1d(2.0 * point.0 / radius.0, b, c)
+            * Self::mitchell_1d(2.0 * point.1 / radius.1, b, c)
+    }
+}
+
+impl Default for MitchellFilter {
+    fn default() -> Self {
+        Self::new((2.0, 2.0), 1.0 / 3.0, 1.0 / 3.0)
+    }
+}
+
+impl Filter for MitchellFilter {
+    fn radius(&self) -> (f64, f64) {
+        self.radius
+    }
+
+    fn evaluate(&self, point: (f64, f64)) -> f64 {
+        Self::mitchell_2d(point, self.radius, self.b, self.c)
+    }
+
+    fn sample(&self, sample: (f64, f64)) -> FilterSample {
+        self.sampler.sample(sample, |point| self.evaluate(point))
+    }
+}
+
+// Sinc windowed by a wider sinc with `tau` lobes
+pub struct LanczosFilter {
+    radius: (f64, f64),
+    tau: f64,
+    sampler: TabulatedFilterSampler,
+}
+
+impl LanczosFilter {
+    pub fn new(radius: (f64, f64), tau: f64) -> Self {
+        let evaluate = |point: (f64, f64)| Self::lanczos_2d(point, radius, tau);
+        Self {
+            radius,
+            tau,
+            sampler: TabulatedFilterSampler::new(radius, evaluate),
+        }
+    }
+
+    fn sinc(x: f64) -> f64 {
+        if f64::abs(x) < 1e-5 {
+            return 1.0;
+        }
+        f64::sin(consts::PI * x) / (consts::PI * x)
+    }
+
+    fn windowed_sinc(x: f64, radius: f64, tau: f64) -> f64 {
+        if f64::abs(x) > radius {
+            return 0.0;
+        }
+        Self::sinc(x) * Self::sinc(x / tau)
+    }
+
+    fn lanczos_2d(point: (f64, f64), radius: (f64, f64), tau: f64) -> f64 {
+        Self::windowed_sinc(point.0, radius.0, tau) * Self::windowed_sinc(point.1, radius.1, tau)
+    }
+}
+
+impl Default for LanczosFilter {
+    fn default() -> Self {
+        Self::new((4.0, 4.0), 3.0)
+    }
+}
+
+impl Filter for LanczosFilter {
+    fn radius(&self) -> (f64, f64) {
+        self.radius
+    }
+
+    fn evaluate(&self, point: (f64, f64)) -> f64 {
+        Self::lanczos_2d(point, self.radius, self.tau)
+    }
+
+    fn sample(&self, sample: (f64, f64)) -> FilterSample {
+        self.sampler.sample(sample, |point| self.evaluate(point))
+    }
+}
+
+// Piecewise constant approximation of |filter| over a grid, for filters that
+// can't be sampled analytically. The returned weight corrects for both the
+// approximation and the sign of the filter.
+struct TabulatedFilterSampler {
+    radius: (f64, f64),
+    resolution: (usize, usize),
+    // Cumulative sums of each row, then of the row totals
+    row_cdfs: Vec<Vec<f64>>,
+    marginal_cdf: Vec<f64>,
+    integral: f64,
+}
+
+impl TabulatedFilterSampler {
+    const CELLS_PER_UNIT: f64 = 32.0;
+
+    fn new(radius: (f64, f64), evaluate: impl Fn((f64, f64)) -> f64) -> Self {
+        let resolution = (
+            usize::max(1, (2.0 * radius.0 * Self::CELLS_PER_UNIT) as usize),
+            usize::max(1, (2.0 * radius.1 * Self::CELLS_PER_UNIT) as usize),
+        );
+        let cell_size = (
+            2.0 * radius.0 / resolution.0 as f64,
+            2.0 * radius.1 / resolution.1 as f64,
+        );
+
+        let mut row_cdfs = Vec::with_capacity(resolution.1);
+        let mut marginal_cdf = Vec::with_capacity(resolution.1);
+        let mut total = 0.0;
+        for row in 0..resolution.1 {
+            let y = -radius.1 + (row as f64 + 0.5) * cell_size.1;
+            let mut row_total = 0.0;
+            let row_cdf = (0..resolution.0)
+                .map(|column| {
+                    let x = -radius.0 + (column as f64 + 0.5) * cell_size.0;
+                    row_total += f64::abs(evaluate((x, y)));
+                    row_total
+                })
+                .collect();
+            row_cdfs.push(row_cdf);
+            total += row_total;
+            marginal_cdf.push(total);
+        }
+
+        Self {
+            radius,
+            resolution,
+            row_cdfs,
+            marginal_cdf,
+            integral: total * cell_size.0 * cell_size.1,
+        }
+    }
+
+    fn sample(&self, sample: (f64, f64), evaluate: impl Fn((f64, f64)) -> f64) -> FilterSample {
+        let (row, y_fraction) = Self::sample_cdf(&self.marginal_cdf, sample.1);
+        let (column, x_fraction) = Self::sample_cdf(&self.row_cdfs[row], sample.0);
+
+        let cell_size = (
+            2.0 * self.radius.0 / self.resolution.0 as f64,
+            2.0 * self.radius.1 / self.resolution.1 as f64,
+        );
+        let offset = (
+            -self.radius.0 + (column as f64 + x_fraction) * cell_size.0,
+            -self.radius.1 + (row as f64 + y_fraction) * cell_size.1,
+        );
+
+        let previous = if column == 0 {
+            0.0
+        } else {
+            self.row_cdfs[row][column - 1]
+        };
+        let cell_value = self.row_cdfs[row][column] - previous;
+        let pdf = cell_value / self.integral;
+        let value = evaluate(offset);
+
+        FilterSample {
+            offset,
+            weight: if pdf > 0.0 { value / pdf } else { 0.0 },
+        }
+    }
+
+    // Index of the bucket `sample` falls in and how far into it
+    fn sample_cdf(cdf: &[f64], sample: f64) -> (usize, f64) {
+        let total = cdf[cdf.len() - 1];
+        let target = sample * total;
+        let index = usize::min(cdf.partition_point(|value| *value <= target), cdf.len() - 1);
+        let start = if index == 0 { 0.0 } else { cdf[index - 1] };
+        let width = cdf[index] - start;
+        let fraction = if width > 0.0 {
+            (target - start) / width
+        } else {
+            0.5
+        };
+
+        (index, f64::clamp(fraction, 0.0, 1.0))
+    }
+}
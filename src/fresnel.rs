@@ -0,0 +1,347 @@
+use crate::vec3;
+
+// Complex index of refraction `eta + i k` of a conductor, per RGB channel
+#[derive(Clone)]
+pub struct ComplexIor {
+    pub eta: vec3::Color,
+    pub k: vec3::Color,
+}
+
+impl ComplexIor {
+    pub const PRESET_NAMES: [&'static str; 5] = ["gold", "copper", "silver", "aluminium", "chrome"];
+
+    pub fn new(eta: vec3::Color, k: vec3::Color) -> Self {
+        Self { eta, k }
+    }
+
+    // Measured metals, looked up by name ignoring case
+    pub fn preset(name: &str) -> Option<Self> {
+        let (eta, k) = match name.to_ascii_lowercase().as_str() {
+            "gold" => ((0.143119, 0.374957, 1.44248), (3.98316, 2.38572, 1.60322)),
+            "copper" => ((0.200438, 0.924033, 1.10221), (3.91295, 2.45285, 2.14219)),
+            "silver" => ((0.155265, 0.116723, 0.138342), (4.82835, 3.12225, 2.14696)),
+            "aluminium" | "aluminum" => ((1.65746, 0.880369, 0.521229), (9.22387, 6.26952, 4.837)),
+            "chrome" | "chromium" => ((4.36968, 2.9167, 1.6547), (5.20643, 4.23136, 3.75495)),
+            _ => return None,
+        };
+
+        Some(Self::new(
+            vec3::Color::new(eta.0, eta.1, eta.2),
+            vec3::Color::new(k.0, k.1, k.2),
+        ))
+    }
+
+    // Conductor reflecting `reflectivity` at normal incidence and tending
+    // towards `edge_tint` near grazing angles (Gulbrandsen 2014)
+    pub fn from_reflectance(reflectivity: &vec3::Color, edge_tint: &vec3::Color) -> Self {
+        let channel = |r: f64, g: f64| {
+            let r = f64::clamp(r, 0.0, 0.99);
+            let sqrt_r = f64::sqrt(r);
+            let eta = g * (1.0 - r) / (1.0 + r) + (1.0 - g) * (1.0 + sqrt_r) / (1.0 - sqrt_r);
+            let k_squared = (r * (eta + 1.0) * (eta + 1.0) - (eta - 1.0) * (eta - 1.0)) / (1.0 - r);
+            (eta, f64::sqrt(f64::max(k_squared, 0.0)))
+        };
+        let (red, green, blue) = (
+            channel(reflectivity.x(), edge_tint.x()),
+            channel(reflectivity.y(), edge_tint.y()),
+            channel(reflectivity.z(), edge_tint.z()),
+        );
+
+        Self::new(
+            vec3::Color::new(red.0, green.0, blue.0),
+            vec3::Color::new(red.1, green.1, blue.1),
+        )
+    }
+
+    // Non absorbing dielectric of the given IOR
+    pub fn dielectric(index_of_refraction: f64) -> Self {
+        Self::new(
+            vec3::Color::new(
+                index_of_refraction,
+                index_of_refraction,
+                index_of_refraction,
+            ),
+            vec3::Color::zeroed(),
+        )
+    }
+}
+
+// How a conductor's reflectance varies with the angle of incidence
+#[derive(Clone)]
+pub enum ConductorFresnel {
+    // Schlick's approximation from the reflectance at normal incidence
+    Schlick(vec3::Color),
+    Complex(ComplexIor),
+}
+
+impl ConductorFresnel {
+    pub fn evaluate(&self, cos_theta: f64) -> vec3::Color {
+        let cos_theta = f64::clamp(cos_theta, 0.0, 1.0);
+        match self {
+            Self::Schlick(f0) => {
+                let weight = f64::powi(1.0 - cos_theta, 5);
+                crate::lerp(weight, f0.clone(), vec3::Color::new(1.0, 1.0, 1.0))
+            }
+            Self::Complex(ior) => vec3::Color::new(
+                conductor(cos_theta, ior.eta.x(), ior.k.x()),
+                conductor(cos_theta, ior.eta.y(), ior.k.y()),
+                conductor(cos_theta, ior.eta.z(), ior.k.z()),
+            ),
+        }
+    }
+}
+
+// Exact unpolarized reflectance of a conductor with complex IOR `eta + i k`
+pub fn conductor(cos_theta: f64, eta: f64, k: f64) -> f64 {
+    let cos_squared = cos_theta * cos_theta;
+    let sin_squared = 1.0 - cos_squared;
+
+    let t0 = eta * eta - k * k - sin_squared;
+    let a_squared_plus_b_squared = f64::sqrt(t0 * t0 + 4.0 * eta * eta * k * k);
+    let a = f64::sqrt(f64::max(0.0, 0.5 * (a_squared_plus_b_squared + t0)));
+
+    let t1 = a_squared_plus_b_squared + cos_squared;
+    let t2 = 2.0 * a * cos_theta;
+    let perpendicular = (t1 - t2) / (t1 + t2);
+
+    let t3 = cos_squared * a_squared_plus_b_squared + sin_squared * sin_squared;
+    let t4 = t2 * sin_squared;
+    let parallel = perpendicular * (t3 - t4) / (t3 + t4);
+
+    0.5 * (perpendicular + parallel)
+}
+
+// Exact unpolarized reflectance of a dielectric interface, `eta` being the IOR
+// on the far side of the normal over the IOR on the near side. Negative
+// cosines come from the far side.
+pub fn dielectric(cos_theta: f64, eta: f64) -> f64 {
+    let (cos_theta_i, eta) = if cos_theta < 0.0 {
+        (f64::min(-cos_theta, 1.0), 1.0 / eta)
+    } else {
+        (f64::min(cos_theta, 1.0), eta)
+    };
+
+    let sin_squared_t = (1.0 - cos_theta_i * cos_theta_i) / (eta * eta);
+    if sin_squared_t >= 1.0 {
+        // Total internal reflection
+        return 1.0;
+    }
+    let cos_theta_t = f64::sqrt(1.0 - sin_squared_t);
+
+    let parallel = (eta * cos_theta_i - cos_theta_t) / (eta * cos_theta_i + cos_theta_t);
+    let perpendicular = (cos_theta_i - eta * cos_theta_t) / (cos_theta_i + eta * cos_theta_t);
+    0.5 * (parallel * parallel + perpendicular * perpendicular)
+}
+
+// Transparent film a few hundred nanometers thick on top of a surface, like
+// soap or oil. Light reflected off its top and bottom interferes, tinting
+// reflections depending on the angle.
+#[derive(Clone, Copy, Debug)]
+pub struct ThinFilm {
+    // In nanometers
+    pub thickness: f64,
+    pub index_of_refraction: f64,
+}
+
+impl ThinFilm {
+    pub fn new(thickness: f64, index_of_refraction: f64) -> Self {
+        Self {
+            thickness,
+            index_of_refraction,
+        }
+    }
+
+    // Unpolarized reflectance at `wavelength` of the film lying between a
+    // medium of IOR `outside`, where the light comes from, and a substrate of
+    // complex IOR `eta + i k`. Sums every reflection inside the film (Airy).
+    pub fn reflectance(
+        &self,
+        cos_theta: f64,
+        outside: f64,
+        eta: f64,
+        k: f64,
+        wavelength: f64,
+    ) -> f64 {
+        let cos_theta = f64::clamp(cos_theta, 0.0, 1.0);
+        let sin_squared = outside * outside * (1.0 - cos_theta * cos_theta);
+        let n0 = Complex::real(outside);
+        let n1 = Complex::real(self.index_of_refraction);
+        let n2 = Complex::new(eta, k);
+        // IOR times the cosine of the angle inside each layer, imaginary for
+        // evanescent waves
+        let q0 = Complex::real(outside * cos_theta);
+        let q1 = (n1 * n1 - Complex::real(sin_squared)).sqrt();
+        let q2 = (n2 * n2 - Complex::real(sin_squared)).sqrt();
+
+        // Round trip through the film
+        let phase_shift =
+            Complex::new(0.0, 4.0 * crate::consts::PI * self.thickness / wavelength) * q1;
+        let round_trip = phase_shift.exp();
+
+        let airy = |top: Complex, bottom: Complex| {
+            ((top + bottom * round_trip) / (Complex::real(1.0) + top * bottom * round_trip))
+                .norm_squared()
+        };
+        let perpendicular = airy((q0 - q1) / (q0 + q1), (q1 - q2) / (q1 + q2));
+        let parallel = airy(
+            (n1 * n1 * q0 - n0 * n0 * q1) / (n1 * n1 * q0 + n0 * n0 * q1),
+            (n2 * n2 * q1 - n1 * n1 * q2) / (n2 * n2 * q1 + n1 * n1 * q2),
+        );
+
+        f64::clamp(0.5 * (perpendicular + parallel), 0.0, 1.0)
+    }
+
+    // Reflectance at `wavelength` for every channel when the path has one,
+    // otherwise averaged over the band of each channel
+    pub fn reflectance_rgb(
+        &self,
+        cos_theta: f64,
+        outside: f64,
+        substrate: &ComplexIor,
+        wavelength: Option<f64>,
+    ) -> vec3::Color {
+        const BAND_SAMPLES: usize = 8;
+
+        let channel = |index: usize| {
+            let (eta, k) = (substrate.eta[index], substrate.k[index]);
+            if let Some(wavelength) = wavelength {
+                return self.reflectance(cos_theta, outside, eta, k, wavelength);
+            }
+            let (start, end) = crate::spectrum::CHANNEL_BANDS[index];
+            (0..BAND_SAMPLES)
+                .map(|sample| {
+                    let wavelength =
+                        start + (sample as f64 + 0.5) / BAND_SAMPLES as f64 * (end - start);
+                    self.reflectance(cos_theta, outside, eta, k, wavelength)
+                })
+                .sum::<f64>()
+                / BAND_SAMPLES as f64
+        };
+
+        vec3::Color::new(channel(0), channel(1), channel(2))
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Complex {
+    re: f64,
+    im: f64,
+}
+
+impl Complex {
+    fn new(re: f64, im: f64) -> Self {
+        Self { re, im }
+    }
+
+    fn real(re: f64) -> Self {
+        Self::new(re, 0.0)
+    }
+
+    fn norm_squared(&self) -> f64 {
+        self.re * self.re + self.im * self.im
+    }
+
+    // Principal square root, never with a negative real part
+    fn sqrt(&self) -> Self {
+        let norm = f64::sqrt(self.norm_squared());
+        let re = f64::sqrt(f64::max(0.5 * (norm + self.re), 0.0));
+        let im = f64::sqrt(f64::max(0.5 * (norm - self.re), 0.0));
+        Self::new(re, if self.im < 0.0 { -im } else { im })
+    }
+
+    fn exp(&self) -> Self {
+        let magnitude = f64::exp(self.re);
+        Self::new(magnitude * f64::cos(self.im), magnitude * f64::sin(self.im))
+    }
+}
+
+impl std::ops::Add for Complex {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self::Output {
+        Self::new(self.re + rhs.re, self.im + rhs.im)
+    }
+}
+
+impl std::ops::Sub for Complex {
+    type Output = Self;
+    fn sub(self, rhs: Self) -> Self::Output {
+        Self::new(self.re - rhs.re, self.im - rhs.im)
+    }
+}
+
+impl std::ops::Mul for Complex {
+    type Output = Self;
+    fn mul(self, rhs: Self) -> Self::Output {
+        Self::new(
+            self.re * rhs.re - self.im * rhs.im,
+            self.re * rhs.im + self.im * rhs.re,
+        )
+    }
+}
+
+impl std::ops::Div for Complex {
+    type Output = Self;
+    fn div(self, rhs: Self) -> Self::Output {
+        let denominator = rhs.norm_squared();
+        Self::new(
+            (self.re * rhs.re + self.im * rhs.im) / denominator,
+            (self.im * rhs.re - self.re * rhs.im) / denominator,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COSINES: [f64; 5] = [1.0, 0.8, 0.5, 0.2, 0.05];
+
+    #[test]
+    fn film_without_thickness_is_dielectric_fresnel() {
+        let film = ThinFilm::new(0.0, 1.33);
+        for cos_theta in COSINES {
+            for wavelength in [400.0, 550.0, 700.0] {
+                let with_film = film.reflectance(cos_theta, 1.0, 1.5, 0.0, wavelength);
+                let bare = dielectric(cos_theta, 1.5);
+                assert!(
+                    (with_film - bare).abs() < 1e-9,
+                    "cos {cos_theta} at {wavelength} nm: {with_film} != {bare}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn film_without_thickness_is_conductor_fresnel() {
+        let film = ThinFilm::new(0.0, 1.5);
+        let gold = ComplexIor::preset("gold").unwrap();
+        for cos_theta in COSINES {
+            let with_film = film.reflectance_rgb(cos_theta, 1.0, &gold, None);
+            for channel in 0..3 {
+                let bare = conductor(cos_theta, gold.eta[channel], gold.k[channel]);
+                assert!(
+                    (with_film[channel] - bare).abs() < 1e-9,
+                    "cos {cos_theta}, channel {channel}: {} != {bare}",
+                    with_film[channel]
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn film_reflectance_oscillates_with_thickness() {
+        // Soap film in air, reflectance swings between interfering orders
+        let reflectances: Vec<f64> = (0..40)
+            .map(|step| {
+                ThinFilm::new(step as f64 * 25.0, 1.33).reflectance(1.0, 1.0, 1.0, 0.0, 550.0)
+            })
+            .collect();
+        let max = reflectances.iter().cloned().fold(0.0, f64::max);
+        let min = reflectances.iter().cloned().fold(1.0, f64::min);
+        assert!(
+            min < 1e-9,
+            "Air on both sides of a vanishing film reflects nothing"
+        );
+        assert!(max > 0.05, "Quarter wave films reflect, got at most {max}");
+    }
+}
@@ -13,7 +13,7 @@ impl<'a> PPMImage<'a> {
         width: usize,
         height: usize,
         max_color: u32,
-    ) -> PPMImage {
+    ) -> PPMImage<'a> {
         PPMImage {
             height,
             width,
@@ -77,3 +77,351 @@ impl<'a> From<PPMImage<'a>> for String {
         str
     }
 }
+
+const CHECKPOINT_MAGIC: &[u8; 8] = b"RTCKPT01";
+
+// What a checkpoint has to match before a render can resume from it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CheckpointHeader {
+    pub sampler_seed: u64,
+    pub scene_hash: u64,
+}
+
+// Running per pixel weighted sums of the samples traced so far, stored row by row
+pub struct AccumulationBuffer {
+    width: usize,
+    height: usize,
+    sums: Vec<super::vec3::Color>,
+    weight_sums: Vec<f64>,
+    sample_counts: Vec<u32>,
+    // Welford running mean and sum of squared deviations of the weighted sample luminance
+    luminance_means: Vec<f64>,
+    luminance_m2: Vec<f64>,
+}
+
+impl AccumulationBuffer {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            sums: vec![super::vec3::Color::zeroed(); width * height],
+            weight_sums: vec![0.0; width * height],
+            sample_counts: vec![0; width * height],
+            luminance_means: vec![0.0; width * height],
+            luminance_m2: vec![0.0; width * height],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn add_sample(
+        &mut self,
+        row: usize,
+        column: usize,
+        color: super::vec3::Color,
+        weight: f64,
+    ) {
+        let index = row * self.width + column;
+        let luminance = weight * color.luminance();
+        self.sums[index] += weight * color;
+        self.weight_sums[index] += weight;
+        self.sample_counts[index] += 1;
+
+        let delta = luminance - self.luminance_means[index];
+        self.luminance_means[index] += delta / self.sample_counts[index] as f64;
+        self.luminance_m2[index] += delta * (luminance - self.luminance_means[index]);
+    }
+
+    pub fn sample_count(&self, row: usize, column: usize) -> u32 {
+        self.sample_counts[row * self.width + column]
+    }
+
+    pub fn luminance_variance(&self, row: usize, column: usize) -> f64 {
+        let index = row * self.width + column;
+        if self.sample_counts[index] < 2 {
+            return 0.0;
+        }
+        self.luminance_m2[index] / (self.sample_counts[index] - 1) as f64
+    }
+
+    // Standard error of the pixel's luminance as seen after gamma correction,
+    // None while there are too few samples to tell
+    pub fn pixel_noise(&self, row: usize, column: usize) -> Option<f64> {
+        let index = row * self.width + column;
+        if self.sample_counts[index] < 2 {
+            return None;
+        }
+        let mean_weight = self.weight_sums[index] / self.sample_counts[index] as f64;
+        if mean_weight <= 0.0 {
+            return None;
+        }
+        let mean = self.luminance_means[index] / mean_weight;
+        if mean <= 0.0 {
+            return Some(0.0);
+        }
+        let standard_error =
+            f64::sqrt(self.luminance_variance(row, column) / self.sample_counts[index] as f64)
+                / mean_weight;
+
+        // d(sqrt(x)) = dx / (2 * sqrt(x)), see linear_space_to_gamma_space
+        Some(standard_error / (2.0 * f64::sqrt(mean)))
+    }
+
+    // Weighted average of the samples of a pixel, still in linear space
+    pub fn pixel_color(&self, row: usize, column: usize) -> super::vec3::Color {
+        let index = row * self.width + column;
+        if self.weight_sums[index] == 0.0 {
+            return super::vec3::Color::zeroed();
+        }
+        &self.sums[index] / self.weight_sums[index]
+    }
+
+    // Copy of the pixels inside `region`, as an image of the region's size
+    pub fn crop(&self, region: &super::tile::Tile) -> Self {
+        let mut cropped = Self::new(
+            region.column_end - region.column_start,
+            region.row_end - region.row_start,
+        );
+        for row in region.row_start..region.row_end {
+            let from = row * self.width + region.column_start;
+            let to = (row - region.row_start) * cropped.width;
+            let width = cropped.width;
+            cropped.sums[to..to + width].clone_from_slice(&self.sums[from..from + width]);
+            cropped.weight_sums[to..to + width]
+                .copy_from_slice(&self.weight_sums[from..from + width]);
+            cropped.sample_counts[to..to + width]
+                .copy_from_slice(&self.sample_counts[from..from + width]);
+            cropped.luminance_means[to..to + width]
+                .copy_from_slice(&self.luminance_means[from..from + width]);
+            cropped.luminance_m2[to..to + width]
+                .copy_from_slice(&self.luminance_m2[from..from + width]);
+        }
+        cropped
+    }
+
+    // Replaces the pixels inside `region` by those of `other`, an image of the
+    // same size, keeping the current ones where `other` has no samples
+    pub fn replace_sampled(&mut self, other: &Self, region: &super::tile::Tile) {
+        debug_assert!(
+            self.width == other.width && self.height == other.height,
+            "Images must be the same size"
+        );
+        for row in region.row_start..region.row_end {
+            for index in
+                row * self.width + region.column_start..row * self.width + region.column_end
+            {
+                if other.sample_counts[index] == 0 {
+                    continue;
+                }
+                self.sums[index] = other.sums[index].clone();
+                self.weight_sums[index] = other.weight_sums[index];
+                self.sample_counts[index] = other.sample_counts[index];
+                self.luminance_means[index] = other.luminance_means[index];
+                self.luminance_m2[index] = other.luminance_m2[index];
+            }
+        }
+    }
+
+    // Loads a P3 image written by `write_ppm`, every pixel counting as a single sample
+    pub fn read_ppm(reader: &mut impl std::io::Read) -> std::io::Result<Self> {
+        let invalid = |message: &str| std::io::Error::new(std::io::ErrorKind::InvalidData, message);
+
+        let mut text = String::new();
+        reader.read_to_string(&mut text)?;
+        let mut tokens = text
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or(""))
+            .flat_map(str::split_whitespace);
+
+        if tokens.next() != Some("P3") {
+            return Err(invalid("Only P3 images are supported"));
+        }
+        let mut next_number = || -> std::io::Result<usize> {
+            tokens
+                .next()
+                .and_then(|token| token.parse().ok())
+                .ok_or_else(|| invalid("Truncated or malformed image"))
+        };
+        let width = next_number()?;
+        let height = next_number()?;
+        let max_color = next_number()?.max(1) as f64;
+
+        let mut buffer = Self::new(width, height);
+        for row in 0..height {
+            for column in 0..width {
+                let mut channel = || -> std::io::Result<f64> {
+                    // Inverse of linear_space_to_gamma_space
+                    let gamma = next_number()? as f64 / max_color;
+                    Ok(gamma * gamma)
+                };
+                let color = super::vec3::Color::new(channel()?, channel()?, channel()?);
+                buffer.add_sample(row, column, color, 1.0);
+            }
+        }
+        Ok(buffer)
+    }
+
+    pub fn write_ppm(&self, writer: &mut impl std::io::Write) -> std::io::Result<()> {
+        writeln!(writer, "P3")?;
+        writeln!(writer, "{} {}", self.width, self.height)?;
+        writeln!(writer, "255")?;
+        for row in 0..self.height {
+            for column in 0..self.width {
+                self.pixel_color(row, column).write_color(writer, 1.0);
+            }
+        }
+        writer.flush()
+    }
+
+    // Writes to a temporary file first so readers never see a half written image
+    pub fn write_ppm_file(&self, path: &std::path::Path) -> std::io::Result<()> {
+        let temp_path = path.with_extension("ppm.tmp");
+        let mut writer = std::io::BufWriter::new(std::fs::File::create(&temp_path)?);
+        self.write_ppm(&mut writer)?;
+        drop(writer);
+        std::fs::rename(temp_path, path)
+    }
+
+    // Saves everything needed to keep adding samples later, see `read_checkpoint`
+    pub fn write_checkpoint(
+        &self,
+        writer: &mut impl std::io::Write,
+        header: &CheckpointHeader,
+    ) -> std::io::Result<()> {
+        writer.write_all(CHECKPOINT_MAGIC)?;
+        for value in [
+            self.width as u64,
+            self.height as u64,
+            header.sampler_seed,
+            header.scene_hash,
+        ] {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        for index in 0..self.width * self.height {
+            for value in [
+                self.sums[index].x(),
+                self.sums[index].y(),
+                self.sums[index].z(),
+                self.weight_sums[index],
+                self.luminance_means[index],
+                self.luminance_m2[index],
+            ] {
+                writer.write_all(&value.to_le_bytes())?;
+            }
+            writer.write_all(&self.sample_counts[index].to_le_bytes())?;
+        }
+        writer.flush()
+    }
+
+    pub fn write_checkpoint_file(
+        &self,
+        path: &std::path::Path,
+        header: &CheckpointHeader,
+    ) -> std::io::Result<()> {
+        let temp_path = path.with_extension("tmp");
+        let mut writer = std::io::BufWriter::new(std::fs::File::create(&temp_path)?);
+        self.write_checkpoint(&mut writer, header)?;
+        drop(writer);
+        std::fs::rename(temp_path, path)
+    }
+
+    // Fails with `InvalidData` unless the checkpoint is `width` by `height`,
+    // checked before anything gets allocated
+    pub fn read_checkpoint(
+        reader: &mut impl std::io::Read,
+        width: usize,
+        height: usize,
+    ) -> std::io::Result<(Self, CheckpointHeader)> {
+        let mut magic = [0; CHECKPOINT_MAGIC.len()];
+        reader.read_exact(&mut magic)?;
+        if magic != *CHECKPOINT_MAGIC {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "Not a render checkpoint",
+            ));
+        }
+
+        fn read_u64(reader: &mut impl std::io::Read) -> std::io::Result<u64> {
+            let mut bytes = [0; 8];
+            reader.read_exact(&mut bytes)?;
+            Ok(u64::from_le_bytes(bytes))
+        }
+        let (stored_width, stored_height) = (read_u64(reader)?, read_u64(reader)?);
+        if stored_width != width as u64 || stored_height != height as u64 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Checkpoint is {stored_width}x{stored_height}, expected {width}x{height}"),
+            ));
+        }
+        let pixel_count = width
+            .checked_mul(height)
+            .filter(|pixel_count| *pixel_count > 0)
+            .ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("Invalid checkpoint size {width}x{height}"),
+                )
+            })?;
+        let header = CheckpointHeader {
+            sampler_seed: read_u64(reader)?,
+            scene_hash: read_u64(reader)?,
+        };
+
+        let mut buffer = Self::new(width, height);
+        for index in 0..pixel_count {
+            let mut values = [0.0; 6];
+            for value in &mut values {
+                *value = f64::from_bits(read_u64(reader)?);
+            }
+            let [r, g, b, weight_sum, luminance_mean, luminance_m2] = values;
+            buffer.sums[index] = super::vec3::Color::new(r, g, b);
+            buffer.weight_sums[index] = weight_sum;
+            buffer.luminance_means[index] = luminance_mean;
+            buffer.luminance_m2[index] = luminance_m2;
+
+            let mut count = [0; 4];
+            reader.read_exact(&mut count)?;
+            buffer.sample_counts[index] = u32::from_le_bytes(count);
+        }
+        Ok((buffer, header))
+    }
+
+    // Samples taken per pixel, from blue for the fewest to red for the most
+    pub fn write_sample_heatmap(&self, writer: &mut impl std::io::Write) -> std::io::Result<()> {
+        let max_samples = self.sample_counts.iter().copied().max().unwrap_or(0).max(1) as f64;
+
+        writeln!(writer, "P3")?;
+        writeln!(writer, "{} {}", self.width, self.height)?;
+        writeln!(writer, "255")?;
+        for count in &self.sample_counts {
+            let color = super::lerp(
+                *count as f64 / max_samples,
+                super::vec3::Color::new(0.0, 0.0, 1.0),
+                super::vec3::Color::new(1.0, 0.0, 0.0),
+            );
+            write_linear_color(writer, &color)?;
+        }
+        writer.flush()
+    }
+}
+
+// Writes the color as PPM bytes without gamma correction, for images that
+// encode data rather than light
+fn write_linear_color(
+    writer: &mut impl std::io::Write,
+    color: &super::vec3::Color,
+) -> std::io::Result<()> {
+    let byte = |component: f64| f64::round(f64::clamp(component, 0.0, 1.0) * 255.0) as u8;
+    write!(
+        writer,
+        "{} {} {} ",
+        byte(color.x()),
+        byte(color.y()),
+        byte(color.z())
+    )
+}
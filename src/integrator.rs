@@ -0,0 +1,716 @@
+use crate::{
+    consts,
+    hittable::{self, Hittable},
+    lerp, material,
+    pdf::{self, Pdf},
+    ray, sampler, spectrum, stats, vec3,
+};
+
+pub trait Integrator {
+    fn ray_color(
+        &self,
+        ray: ray::Ray,
+        world: &hittable::HittableObjects,
+        lights: &hittable::HittableObjects,
+        sampler: &mut dyn sampler::Sampler,
+    ) -> vec3::Color;
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MisHeuristic {
+    Balance,
+    Power,
+}
+
+impl MisHeuristic {
+    // Weight of a sample taken with `pdf` when `other_pdf` could also have generated it
+    pub fn weight(&self, pdf: f64, other_pdf: f64) -> f64 {
+        let (a, b) = match self {
+            Self::Balance => (pdf, other_pdf),
+            Self::Power => (pdf * pdf, other_pdf * other_pdf),
+        };
+        if a + b <= 0.0 {
+            return 0.0;
+        }
+        a / (a + b)
+    }
+}
+
+// Unidirectional path tracer combining material and light sampling
+pub struct PathIntegrator {
+    pub max_bounces: u32,
+    pub mis_heuristic: MisHeuristic,
+    // Bounces traced before paths become candidates for russian roulette
+    pub russian_roulette_depth: u32,
+}
+
+impl Default for PathIntegrator {
+    fn default() -> Self {
+        Self {
+            max_bounces: 10,
+            mis_heuristic: MisHeuristic::Power,
+            russian_roulette_depth: 3,
+        }
+    }
+}
+
+impl Integrator for PathIntegrator {
+    fn ray_color(
+        &self,
+        ray: ray::Ray,
+        world: &hittable::HittableObjects,
+        lights: &hittable::HittableObjects,
+        sampler: &mut dyn sampler::Sampler,
+    ) -> vec3::Color {
+        self.trace_path(&mut RgbTransport, ray, world, lights, sampler)
+    }
+}
+
+impl PathIntegrator {
+    // Bounce loop shared by the RGB and spectral path tracers
+    fn trace_path<T: PathTransport>(
+        &self,
+        transport: &mut T,
+        mut ray: ray::Ray,
+        world: &hittable::HittableObjects,
+        lights: &hittable::HittableObjects,
+        sampler: &mut dyn sampler::Sampler,
+    ) -> T::Radiance {
+        let mut radiance = transport.constant(0.0);
+        let mut throughput = transport.constant(1.0);
+        // MIS weight of any light hit by the current ray, computed at the vertex that sampled it
+        let mut emission_weight = 1.0;
+        let mut segments = 0;
+        let mut termination = stats::Termination::MaxBounces;
+
+        for depth in 0..self.max_bounces {
+            segments = depth + 1;
+            let mut hit_record = hittable::HitRecord::new();
+            if !trace(world, &ray, consts::INFINITY, &mut hit_record) {
+                radiance += throughput * transport.upsample(&background(&ray));
+                termination = stats::Termination::Escaped;
+                break;
+            }
+
+            if ray.absorption().is_some() {
+                throughput = throughput * transport.upsample(&ray.transmittance(hit_record.t));
+            }
+
+            let material = hit_record.material.clone().unwrap();
+            radiance += throughput.clone()
+                * transport.emitted(material.as_ref(), &ray, &hit_record)
+                * emission_weight;
+
+            if material.is_dispersive() && ray.wavelength().is_none() {
+                ray = transport.select_wavelength(ray, &mut throughput, sampler);
+            }
+            let mut scatter_record = material::ScatterRecord::new();
+            record_scatter(material.as_ref());
+            if !material.scatter(&ray, &hit_record, &mut scatter_record, sampler) {
+                termination = stats::Termination::Absorbed;
+                break;
+            }
+
+            let scattered = if scatter_record.is_specular {
+                // Delta lobes can't be hit by light sampling, so their bounce keeps full weight
+                throughput = throughput * transport.upsample(&scatter_record.attenuation);
+                emission_weight = 1.0;
+                let direction = scatter_record.specular_ray.dir();
+                let medium = medium_after(&ray, material.as_ref(), &hit_record, direction);
+                scatter_record
+                    .specular_ray
+                    .with_wavelength(ray.wavelength())
+                    .with_absorption(medium)
+            } else {
+                let bsdf_pdf = scatter_record.pdf.unwrap();
+                if !lights.is_empty() {
+                    radiance += throughput.clone()
+                        * transport.sample_lights(
+                            &ray,
+                            &hit_record,
+                            Some((bsdf_pdf.as_ref(), self.mis_heuristic)),
+                            world,
+                            lights,
+                            sampler,
+                        );
+                }
+
+                let scattered_direction = bsdf_pdf.generate(sampler);
+                let pdf_value = bsdf_pdf.value(&scattered_direction);
+                if pdf_value <= 0.0 {
+                    termination = stats::Termination::Absorbed;
+                    break;
+                }
+                emission_weight = if lights.is_empty() {
+                    1.0
+                } else {
+                    let light_pdf_value = lights.pdf_value(&hit_record.point, &scattered_direction);
+                    self.mis_heuristic.weight(pdf_value, light_pdf_value)
+                };
+
+                throughput = throughput
+                    * transport.upsample(&material.eval(&ray, &hit_record, &scattered_direction))
+                    * (1.0 / pdf_value);
+                let medium =
+                    medium_after(&ray, material.as_ref(), &hit_record, &scattered_direction);
+                ray::Ray::new(hit_record.point.clone(), scattered_direction)
+                    .with_wavelength(ray.wavelength())
+                    .with_absorption(medium)
+            };
+
+            // Paths carrying little energy are randomly terminated, the survivors
+            // are boosted so the estimate stays unbiased
+            if depth + 1 >= self.russian_roulette_depth {
+                let survival_probability = f64::min(T::max_component(&throughput), 1.0);
+                if sampler.get_1d() >= survival_probability {
+                    termination = stats::Termination::RussianRoulette;
+                    break;
+                }
+                throughput /= survival_probability;
+            }
+
+            ray = scattered;
+        }
+        end_path(segments, termination);
+
+        radiance
+    }
+}
+
+// What the path tracers carry along a path, RGB or the values at the sampled
+// wavelengths, and how material and light colors turn into it
+trait PathTransport {
+    type Radiance: Clone
+        + std::ops::Mul<Output = Self::Radiance>
+        + std::ops::Mul<f64, Output = Self::Radiance>
+        + std::ops::AddAssign
+        + std::ops::DivAssign<f64>;
+
+    fn constant(&self, value: f64) -> Self::Radiance;
+    fn upsample(&self, color: &vec3::Color) -> Self::Radiance;
+    fn max_component(radiance: &Self::Radiance) -> f64;
+    fn emitted(
+        &self,
+        material: &dyn material::Material,
+        ray: &ray::Ray,
+        hit_record: &hittable::HitRecord,
+    ) -> Self::Radiance;
+    // Narrows the path down to a single wavelength for dispersive materials
+    fn select_wavelength(
+        &mut self,
+        ray: ray::Ray,
+        throughput: &mut Self::Radiance,
+        sampler: &mut dyn sampler::Sampler,
+    ) -> ray::Ray;
+    fn sample_lights(
+        &self,
+        ray: &ray::Ray,
+        hit_record: &hittable::HitRecord,
+        mis: Option<(&dyn pdf::Pdf, MisHeuristic)>,
+        world: &hittable::HittableObjects,
+        lights: &hittable::HittableObjects,
+        sampler: &mut dyn sampler::Sampler,
+    ) -> Self::Radiance;
+}
+
+struct RgbTransport;
+
+impl PathTransport for RgbTransport {
+    type Radiance = vec3::Color;
+
+    fn constant(&self, value: f64) -> vec3::Color {
+        vec3::Color::new(value, value, value)
+    }
+
+    fn upsample(&self, color: &vec3::Color) -> vec3::Color {
+        color.clone()
+    }
+
+    fn max_component(radiance: &vec3::Color) -> f64 {
+        radiance.max_component()
+    }
+
+    fn emitted(
+        &self,
+        material: &dyn material::Material,
+        ray: &ray::Ray,
+        hit_record: &hittable::HitRecord,
+    ) -> vec3::Color {
+        material.emitted(ray, hit_record)
+    }
+
+    fn select_wavelength(
+        &mut self,
+        ray: ray::Ray,
+        throughput: &mut vec3::Color,
+        sampler: &mut dyn sampler::Sampler,
+    ) -> ray::Ray {
+        select_wavelength(ray, throughput, sampler)
+    }
+
+    fn sample_lights(
+        &self,
+        ray: &ray::Ray,
+        hit_record: &hittable::HitRecord,
+        mis: Option<(&dyn pdf::Pdf, MisHeuristic)>,
+        world: &hittable::HittableObjects,
+        lights: &hittable::HittableObjects,
+        sampler: &mut dyn sampler::Sampler,
+    ) -> vec3::Color {
+        sample_lights(ray, hit_record, mis, world, lights, sampler)
+    }
+}
+
+struct SpectralTransport {
+    wavelengths: spectrum::SampledWavelengths,
+}
+
+impl PathTransport for SpectralTransport {
+    type Radiance = spectrum::SampledSpectrum;
+
+    fn constant(&self, value: f64) -> spectrum::SampledSpectrum {
+        spectrum::SampledSpectrum::constant(value)
+    }
+
+    fn upsample(&self, color: &vec3::Color) -> spectrum::SampledSpectrum {
+        spectrum::SampledSpectrum::from_rgb(color, &self.wavelengths)
+    }
+
+    fn max_component(radiance: &spectrum::SampledSpectrum) -> f64 {
+        radiance.max_component()
+    }
+
+    fn emitted(
+        &self,
+        material: &dyn material::Material,
+        ray: &ray::Ray,
+        hit_record: &hittable::HitRecord,
+    ) -> spectrum::SampledSpectrum {
+        material.emitted_spectrum(ray, hit_record, &self.wavelengths)
+    }
+
+    fn select_wavelength(
+        &mut self,
+        ray: ray::Ray,
+        _throughput: &mut spectrum::SampledSpectrum,
+        _sampler: &mut dyn sampler::Sampler,
+    ) -> ray::Ray {
+        // The other wavelengths would refract elsewhere, the hero carries on alone
+        self.wavelengths.terminate_secondary();
+        ray.with_wavelength(Some(self.wavelengths.hero()))
+    }
+
+    fn sample_lights(
+        &self,
+        ray: &ray::Ray,
+        hit_record: &hittable::HitRecord,
+        mis: Option<(&dyn pdf::Pdf, MisHeuristic)>,
+        world: &hittable::HittableObjects,
+        lights: &hittable::HittableObjects,
+        sampler: &mut dyn sampler::Sampler,
+    ) -> spectrum::SampledSpectrum {
+        sample_lights_spectrum(
+            ray,
+            hit_record,
+            mis,
+            world,
+            lights,
+            &self.wavelengths,
+            sampler,
+        )
+    }
+}
+
+// Path tracer carrying a handful of wavelengths instead of RGB. Material
+// colors are upsampled to spectra along the way and the radiance is converted
+// back to RGB through XYZ, so dispersion and spectral lights come out right.
+pub struct SpectralPathIntegrator {
+    pub max_bounces: u32,
+    pub mis_heuristic: MisHeuristic,
+    // Bounces traced before paths become candidates for russian roulette
+    pub russian_roulette_depth: u32,
+}
+
+impl Default for SpectralPathIntegrator {
+    fn default() -> Self {
+        Self {
+            max_bounces: 10,
+            mis_heuristic: MisHeuristic::Power,
+            russian_roulette_depth: 3,
+        }
+    }
+}
+
+impl Integrator for SpectralPathIntegrator {
+    fn ray_color(
+        &self,
+        ray: ray::Ray,
+        world: &hittable::HittableObjects,
+        lights: &hittable::HittableObjects,
+        sampler: &mut dyn sampler::Sampler,
+    ) -> vec3::Color {
+        let mut transport = SpectralTransport {
+            wavelengths: spectrum::SampledWavelengths::sample_visible(sampler.get_1d()),
+        };
+        let path_integrator = PathIntegrator {
+            max_bounces: self.max_bounces,
+            mis_heuristic: self.mis_heuristic,
+            russian_roulette_depth: self.russian_roulette_depth,
+        };
+        let radiance = path_integrator.trace_path(&mut transport, ray, world, lights, sampler);
+
+        transport.wavelengths.to_rgb(&radiance)
+    }
+}
+
+// Only direct lighting on diffuse surfaces, following specular bounces
+pub struct WhittedIntegrator {
+    pub max_bounces: u32,
+}
+
+impl Default for WhittedIntegrator {
+    fn default() -> Self {
+        Self { max_bounces: 10 }
+    }
+}
+
+impl Integrator for WhittedIntegrator {
+    fn ray_color(
+        &self,
+        mut ray: ray::Ray,
+        world: &hittable::HittableObjects,
+        lights: &hittable::HittableObjects,
+        sampler: &mut dyn sampler::Sampler,
+    ) -> vec3::Color {
+        let mut color = vec3::Color::zeroed();
+        let mut throughput = vec3::Color::new(1.0, 1.0, 1.0);
+        let mut segments = 0;
+        let mut termination = stats::Termination::MaxBounces;
+
+        for depth in 0..self.max_bounces {
+            segments = depth + 1;
+            let mut hit_record = hittable::HitRecord::new();
+            if !trace(world, &ray, consts::INFINITY, &mut hit_record) {
+                color += throughput * background(&ray);
+                termination = stats::Termination::Escaped;
+                break;
+            }
+
+            throughput = throughput * ray.transmittance(hit_record.t);
+
+            let material = hit_record.material.clone().unwrap();
+            color += throughput.clone() * material.emitted(&ray, &hit_record);
+
+            if material.is_dispersive() && ray.wavelength().is_none() {
+                ray = select_wavelength(ray, &mut throughput, sampler);
+            }
+            let mut scatter_record = material::ScatterRecord::new();
+            record_scatter(material.as_ref());
+            if !material.scatter(&ray, &hit_record, &mut scatter_record, sampler) {
+                termination = stats::Termination::Absorbed;
+                break;
+            }
+            if !scatter_record.is_specular {
+                if !lights.is_empty() {
+                    color +=
+                        throughput * sample_lights(&ray, &hit_record, None, world, lights, sampler);
+                }
+                termination = stats::Termination::Shaded;
+                break;
+            }
+
+            throughput = throughput * scatter_record.attenuation;
+            let direction = scatter_record.specular_ray.dir();
+            let medium = medium_after(&ray, material.as_ref(), &hit_record, direction);
+            ray = scatter_record
+                .specular_ray
+                .with_wavelength(ray.wavelength())
+                .with_absorption(medium);
+        }
+        end_path(segments, termination);
+
+        color
+    }
+}
+
+// Shading normals mapped from [-1, 1] to [0, 1]
+pub struct NormalsIntegrator;
+
+impl Integrator for NormalsIntegrator {
+    fn ray_color(
+        &self,
+        ray: ray::Ray,
+        world: &hittable::HittableObjects,
+        _lights: &hittable::HittableObjects,
+        _sampler: &mut dyn sampler::Sampler,
+    ) -> vec3::Color {
+        let mut hit_record = hittable::HitRecord::new();
+        if !trace_first_hit(world, &ray, &mut hit_record) {
+            return vec3::Color::zeroed();
+        }
+
+        0.5 * (hit_record.normal + vec3::Color::new(1.0, 1.0, 1.0))
+    }
+}
+
+// Distance to the first hit, white up close fading to black at `max_distance`
+pub struct DepthIntegrator {
+    pub max_distance: f64,
+}
+
+impl Default for DepthIntegrator {
+    fn default() -> Self {
+        Self { max_distance: 10.0 }
+    }
+}
+
+impl Integrator for DepthIntegrator {
+    fn ray_color(
+        &self,
+        ray: ray::Ray,
+        world: &hittable::HittableObjects,
+        _lights: &hittable::HittableObjects,
+        _sampler: &mut dyn sampler::Sampler,
+    ) -> vec3::Color {
+        let mut hit_record = hittable::HitRecord::new();
+        if !trace_first_hit(world, &ray, &mut hit_record) {
+            return vec3::Color::zeroed();
+        }
+
+        let distance = hit_record.t * ray.dir().length();
+        let intensity = consts::Interval::new(0.0, 1.0).clamp(1.0 - distance / self.max_distance);
+
+        vec3::Color::new(intensity, intensity, intensity)
+    }
+}
+
+// Fraction of the cosine weighted hemisphere left unoccluded within `max_distance`
+pub struct AmbientOcclusionIntegrator {
+    pub samples: u32,
+    pub max_distance: f64,
+}
+
+impl Default for AmbientOcclusionIntegrator {
+    fn default() -> Self {
+        Self {
+            samples: 4,
+            max_distance: 1.0,
+        }
+    }
+}
+
+impl Integrator for AmbientOcclusionIntegrator {
+    fn ray_color(
+        &self,
+        ray: ray::Ray,
+        world: &hittable::HittableObjects,
+        _lights: &hittable::HittableObjects,
+        sampler: &mut dyn sampler::Sampler,
+    ) -> vec3::Color {
+        let mut hit_record = hittable::HitRecord::new();
+        if !trace_first_hit(world, &ray, &mut hit_record) {
+            return vec3::Color::new(1.0, 1.0, 1.0);
+        }
+
+        let hemisphere = pdf::CosinePdf::new(&hit_record.normal);
+        let mut unoccluded = 0;
+        for _ in 0..self.samples {
+            let occlusion_ray =
+                ray::Ray::new(hit_record.point.clone(), hemisphere.generate(sampler));
+            let mut occluder = hittable::HitRecord::new();
+            let max_t = self.max_distance / occlusion_ray.dir().length();
+            if !trace(world, &occlusion_ray, max_t, &mut occluder) {
+                unoccluded += 1;
+            }
+        }
+        // Without any occlusion rays nothing can be found blocking the hit
+        let visibility = if self.samples == 0 {
+            1.0
+        } else {
+            unoccluded as f64 / self.samples as f64
+        };
+
+        vec3::Color::new(visibility, visibility, visibility)
+    }
+}
+
+// Closest hit along the ray up to `max_t`, counted in the render statistics
+pub(crate) fn trace(
+    world: &hittable::HittableObjects,
+    ray: &ray::Ray,
+    max_t: f64,
+    hit_record: &mut hittable::HitRecord,
+) -> bool {
+    stats::record(|statistics| statistics.rays_traced += 1);
+    world.hit(ray, consts::Interval::new(0.001, max_t), hit_record)
+}
+
+// First hit of a camera ray for integrators shading it without bouncing, the
+// path ending there either way
+fn trace_first_hit(
+    world: &hittable::HittableObjects,
+    ray: &ray::Ray,
+    hit_record: &mut hittable::HitRecord,
+) -> bool {
+    let hit = trace(world, ray, consts::INFINITY, hit_record);
+    end_path(
+        1,
+        if hit {
+            stats::Termination::Shaded
+        } else {
+            stats::Termination::Escaped
+        },
+    );
+    hit
+}
+
+// Absorbing medium of a ray leaving the hit towards `direction`. Crossing the
+// surface enters the material or leaves it for the outside, assumed clear,
+// while reflecting stays in the medium the ray came through.
+fn medium_after(
+    ray_in: &ray::Ray,
+    material: &dyn material::Material,
+    hit_record: &hittable::HitRecord,
+    direction: &vec3::Vec3,
+) -> Option<vec3::Color> {
+    if direction.dot(&hit_record.normal) >= 0.0 {
+        ray_in.absorption().cloned()
+    } else if hit_record.front_face {
+        material.absorption(ray_in, hit_record)
+    } else {
+        None
+    }
+}
+
+fn end_path(segments: u32, termination: stats::Termination) {
+    stats::record(|statistics| statistics.end_path(segments, termination));
+}
+
+// Narrows the path down to the single wavelength dispersive materials need,
+// keeping only the matching color channel
+fn select_wavelength(
+    ray: ray::Ray,
+    throughput: &mut vec3::Color,
+    sampler: &mut dyn sampler::Sampler,
+) -> ray::Ray {
+    let (wavelength, channel_weight) = spectrum::sample_channel_wavelength(sampler.get_1d());
+    *throughput = throughput.clone() * channel_weight;
+    ray.with_wavelength(Some(wavelength))
+}
+
+fn record_scatter(material: &dyn material::Material) {
+    stats::record(|statistics| *statistics.scatters.entry(material.name()).or_default() += 1);
+}
+
+pub(crate) fn background(ray: &ray::Ray) -> vec3::Color {
+    let unit_direction = ray.dir().unit_vector();
+    let a = 0.5 * (unit_direction.y() + 1.0); // Normalize values from -1 to 1 to 0 to 1
+
+    lerp(
+        a,
+        vec3::Color::new(1.0, 1.0, 1.0),
+        vec3::Color::new(0.5, 0.7, 1.0),
+    )
+}
+
+// Light sampling half of the MIS estimator, shared by the RGB and spectral integrators
+struct LightSample {
+    shadow_ray: ray::Ray,
+    light_record: hittable::HitRecord,
+    // Material's BSDF times cosine towards the light
+    scattering: vec3::Color,
+    // MIS weight over the light pdf
+    weight: f64,
+}
+
+// Picks a direction towards the lights, None when it can't carry any light
+// back. With `mis` the sample is weighted against the material's own pdf.
+fn sample_light(
+    ray: &ray::Ray,
+    hit_record: &hittable::HitRecord,
+    mis: Option<(&dyn pdf::Pdf, MisHeuristic)>,
+    world: &hittable::HittableObjects,
+    lights: &hittable::HittableObjects,
+    sampler: &mut dyn sampler::Sampler,
+) -> Option<LightSample> {
+    let material = hit_record.material.clone().unwrap();
+    let light_pdf = pdf::HittablePdf::new(lights, hit_record.point.clone());
+
+    let shadow_direction = light_pdf.generate(sampler);
+    let medium = medium_after(ray, material.as_ref(), hit_record, &shadow_direction);
+    let shadow_ray = ray::Ray::new(hit_record.point.clone(), shadow_direction)
+        .with_wavelength(ray.wavelength())
+        .with_absorption(medium);
+    let light_pdf_value = light_pdf.value(shadow_ray.dir());
+    if light_pdf_value <= 0.0 {
+        return None;
+    }
+    let scattering = material.eval(ray, hit_record, shadow_ray.dir());
+    if scattering.near_zero() {
+        return None;
+    }
+
+    let mut light_record = hittable::HitRecord::new();
+    if !trace(world, &shadow_ray, consts::INFINITY, &mut light_record) {
+        return None;
+    }
+
+    let weight = match mis {
+        Some((bsdf_pdf, heuristic)) => {
+            heuristic.weight(light_pdf_value, bsdf_pdf.value(shadow_ray.dir()))
+        }
+        None => 1.0,
+    };
+
+    Some(LightSample {
+        scattering: scattering * shadow_ray.transmittance(light_record.t),
+        shadow_ray,
+        light_record,
+        weight: weight / light_pdf_value,
+    })
+}
+
+// Adds whatever is emitted by the first object in a direction sampled towards the lights
+pub(crate) fn sample_lights(
+    ray: &ray::Ray,
+    hit_record: &hittable::HitRecord,
+    mis: Option<(&dyn pdf::Pdf, MisHeuristic)>,
+    world: &hittable::HittableObjects,
+    lights: &hittable::HittableObjects,
+    sampler: &mut dyn sampler::Sampler,
+) -> vec3::Color {
+    let Some(sample) = sample_light(ray, hit_record, mis, world, lights, sampler) else {
+        return vec3::Color::zeroed();
+    };
+    let emitted = sample
+        .light_record
+        .material
+        .clone()
+        .unwrap()
+        .emitted(&sample.shadow_ray, &sample.light_record);
+
+    sample.weight * sample.scattering * emitted
+}
+
+// `sample_lights` at the wavelengths of a spectral path
+fn sample_lights_spectrum(
+    ray: &ray::Ray,
+    hit_record: &hittable::HitRecord,
+    mis: Option<(&dyn pdf::Pdf, MisHeuristic)>,
+    world: &hittable::HittableObjects,
+    lights: &hittable::HittableObjects,
+    wavelengths: &spectrum::SampledWavelengths,
+    sampler: &mut dyn sampler::Sampler,
+) -> spectrum::SampledSpectrum {
+    let Some(sample) = sample_light(ray, hit_record, mis, world, lights, sampler) else {
+        return spectrum::SampledSpectrum::constant(0.0);
+    };
+    let emitted = sample
+        .light_record
+        .material
+        .clone()
+        .unwrap()
+        .emitted_spectrum(&sample.shadow_ray, &sample.light_record, wavelengths);
+
+    spectrum::SampledSpectrum::from_rgb(&sample.scattering, wavelengths) * emitted * sample.weight
+}
@@ -1,36 +1,82 @@
 pub mod camera;
 pub mod consts;
+pub mod filter;
+pub mod fresnel;
 pub mod image;
+pub mod integrator;
 pub mod material;
+pub mod microfacet;
+pub mod onb;
+pub mod pdf;
+pub mod progress;
+pub mod sampler;
+pub mod spectrum;
+pub mod stats;
+pub mod texture;
+pub mod tile;
 
 pub mod vec3;
 pub mod ray {
-    pub struct Ray<'a> {
-        origin: &'a super::vec3::Point3,
+    #[derive(Clone)]
+    pub struct Ray {
+        origin: super::vec3::Point3,
         dir: super::vec3::Vec3,
+        // In nanometers, only set once a path needs a single wavelength
+        wavelength: Option<f64>,
+        // Of the absorbing medium the ray travels through, if any
+        absorption: Option<super::vec3::Color>,
     }
 
-    impl<'a> Ray<'a> {
-        pub fn new(origin: &'a super::vec3::Point3, dir: super::vec3::Vec3) -> Self {
-            Self { origin, dir }
+    impl Ray {
+        pub fn new(origin: super::vec3::Point3, dir: super::vec3::Vec3) -> Self {
+            Self {
+                origin,
+                dir,
+                wavelength: None,
+                absorption: None,
+            }
+        }
+
+        pub fn with_wavelength(mut self, wavelength: Option<f64>) -> Self {
+            self.wavelength = wavelength;
+            self
+        }
+
+        pub fn wavelength(&self) -> Option<f64> {
+            self.wavelength
+        }
+
+        pub fn with_absorption(mut self, absorption: Option<super::vec3::Color>) -> Self {
+            self.absorption = absorption;
+            self
+        }
+
+        pub fn absorption(&self) -> Option<&super::vec3::Color> {
+            self.absorption.as_ref()
+        }
+
+        // Beer-Lambert falloff through the ray's medium up to `t`
+        pub fn transmittance(&self, t: f64) -> super::vec3::Color {
+            let Some(absorption) = &self.absorption else {
+                return super::vec3::Color::new(1.0, 1.0, 1.0);
+            };
+            let distance = t * self.dir.length();
+            super::vec3::Color::new(
+                f64::exp(-absorption.x() * distance),
+                f64::exp(-absorption.y() * distance),
+                f64::exp(-absorption.z() * distance),
+            )
         }
 
         pub fn origin(&self) -> &super::vec3::Point3 {
-            self.origin
+            &self.origin
         }
         pub fn dir(&self) -> &super::vec3::Vec3 {
             &self.dir
         }
 
         pub fn at(&self, t: f64) -> super::vec3::Vec3 {
-            t * &self.dir + self.origin
-        }
-
-        pub(crate) fn set_origin(&mut self, origin: &'a super::vec3::Point3) {
-            self.origin = origin;
-        }
-        pub(crate) fn set_dir(&mut self, dir: super::vec3::Vec3) {
-            self.dir = dir;
+            t * &self.dir + &self.origin
         }
     }
 }
@@ -87,6 +133,18 @@ pub mod hittable {
             t_interval: consts::Interval,
             hit_rec: &mut HitRecord,
         ) -> bool;
+
+        fn pdf_value(&self, _origin: &vec3::Point3, _direction: &vec3::Vec3) -> f64 {
+            0.0
+        }
+
+        fn random(
+            &self,
+            _origin: &vec3::Point3,
+            _sampler: &mut dyn crate::sampler::Sampler,
+        ) -> vec3::Vec3 {
+            vec3::Vec3::new(1.0, 0.0, 0.0)
+        }
     }
 
     pub struct HittableObjects {
@@ -108,7 +166,13 @@ pub mod hittable {
             self.hittables_vec.push(hittable);
         }
 
-        pub fn hit(
+        pub fn is_empty(&self) -> bool {
+            self.hittables_vec.is_empty()
+        }
+    }
+
+    impl Hittable for HittableObjects {
+        fn hit(
             &self,
             ray: &ray::Ray,
             t_interval: consts::Interval,
@@ -118,6 +182,9 @@ pub mod hittable {
             let mut hit_anything = false;
             let mut closest_so_far = t_interval.max;
 
+            crate::stats::record(|statistics| {
+                statistics.intersection_tests += self.hittables_vec.len() as u64
+            });
             for object in &self.hittables_vec {
                 if object.hit(
                     ray,
@@ -133,6 +200,33 @@ pub mod hittable {
 
             hit_anything
         }
+
+        fn pdf_value(&self, origin: &vec3::Point3, direction: &vec3::Vec3) -> f64 {
+            if self.hittables_vec.is_empty() {
+                return 0.0;
+            }
+            let weight = 1.0 / self.hittables_vec.len() as f64;
+
+            self.hittables_vec
+                .iter()
+                .map(|object| weight * object.pdf_value(origin, direction))
+                .sum()
+        }
+
+        fn random(
+            &self,
+            origin: &vec3::Point3,
+            sampler: &mut dyn crate::sampler::Sampler,
+        ) -> vec3::Vec3 {
+            if self.hittables_vec.is_empty() {
+                return vec3::Vec3::new(1.0, 0.0, 0.0);
+            }
+            let index = usize::min(
+                (sampler.get_1d() * self.hittables_vec.len() as f64) as usize,
+                self.hittables_vec.len() - 1,
+            );
+            self.hittables_vec[index].random(origin, sampler)
+        }
     }
 
     impl Default for HittableObjects {
@@ -201,6 +295,50 @@ pub mod hittable {
 
                 true
             }
+
+            fn pdf_value(
+                &self,
+                origin: &crate::vec3::Point3,
+                direction: &crate::vec3::Vec3,
+            ) -> f64 {
+                let mut hit_rec = super::HitRecord::new();
+                if !self.hit(
+                    &crate::ray::Ray::new(origin.clone(), direction.clone()),
+                    consts::Interval::new(0.001, consts::INFINITY),
+                    &mut hit_rec,
+                ) {
+                    return 0.0;
+                }
+
+                let distance_squared = (&self.center - origin).length_squared();
+                if distance_squared <= self.radius * self.radius {
+                    // Origin inside the sphere, every direction hits it
+                    return 1.0 / (4.0 * consts::PI);
+                }
+                let cos_theta_max = f64::sqrt(1.0 - self.radius * self.radius / distance_squared);
+                let solid_angle = 2.0 * consts::PI * (1.0 - cos_theta_max);
+
+                1.0 / solid_angle
+            }
+
+            fn random(
+                &self,
+                origin: &crate::vec3::Point3,
+                sampler: &mut dyn crate::sampler::Sampler,
+            ) -> crate::vec3::Vec3 {
+                let direction = &self.center - origin;
+                let distance_squared = direction.length_squared();
+                if distance_squared <= self.radius * self.radius {
+                    return crate::vec3::Vec3::sample_unit_vector(sampler.get_2d());
+                }
+                let uvw = crate::onb::Onb::new(&direction);
+
+                uvw.local_vec(&crate::vec3::Vec3::sample_to_sphere(
+                    self.radius,
+                    distance_squared,
+                    sampler.get_2d(),
+                ))
+            }
         }
     }
 }
@@ -214,5 +352,6 @@ pub fn degrees_to_radians(degrees: f64) -> f64 {
 }
 
 pub fn linear_space_to_gamma_space(linear_component: f64) -> f64 {
-    f64::sqrt(linear_component)
+    // Negative lobes of some filters can push pixels below zero
+    f64::sqrt(f64::max(linear_component, 0.0))
 }
@@ -1,6 +1,8 @@
 use hittable::shapes::Sphere;
-use ray_tracing::material::{Dieletric, Lambertian, Metal};
-use ray_tracing::{hittable, vec3};
+use ray_tracing::fresnel::ComplexIor;
+use ray_tracing::material::{Conductor, Dieletric, DiffuseLight, Lambertian, Material, Metal};
+use ray_tracing::spectrum::BlackbodySpectrum;
+use ray_tracing::{hittable, image, integrator, progress, sampler, tile, vec3};
 use std::rc::Rc;
 use vec3::Point3;
 
@@ -11,7 +13,38 @@ fn main() {
     let material_ground = Rc::new(Lambertian::new(vec3::Color::new(0.8, 0.8, 0.0)));
     let material_center = Rc::new(Lambertian::new(vec3::Color::new(0.1, 0.2, 0.5)));
     let material_left = Rc::new(Dieletric::new(1.5));
-    let material_right = Rc::new(Metal::new(vec3::Color::new(0.8, 0.6, 0.2), 0.0));
+    // METAL="name roughness" swaps the right sphere for a measured metal
+    let material_right: Rc<dyn Material> = match std::env::var("METAL") {
+        Ok(metal) => {
+            let mut words = metal.split_whitespace();
+            let name = words.next().unwrap_or_default();
+            let roughness = words.next().map_or(0.0, |roughness| {
+                roughness.parse().expect("Roughness must be a number")
+            });
+            Rc::new(Conductor::preset(name, roughness).unwrap_or_else(|| {
+                panic!(
+                    "Unknown metal {name}, expected one of {:?}",
+                    ComplexIor::PRESET_NAMES
+                )
+            }))
+        }
+        Err(_) => Rc::new(Metal::new(vec3::Color::new(0.8, 0.6, 0.2), 0.0)),
+    };
+    // LIGHT_TEMPERATURE="kelvin" makes the light a black body
+    let material_light = Rc::new(match std::env::var("LIGHT_TEMPERATURE") {
+        Ok(temperature) => {
+            let temperature = temperature
+                .parse()
+                .ok()
+                .filter(|temperature: &f64| temperature.is_finite() && *temperature > 0.0)
+                .unwrap_or_else(|| {
+                    eprintln!("LIGHT_TEMPERATURE must be a positive number of kelvin");
+                    std::process::exit(2);
+                });
+            DiffuseLight::with_spectrum(Box::new(BlackbodySpectrum::new(temperature)), 20.0)
+        }
+        Err(_) => DiffuseLight::new(vec3::Color::new(20.0, 20.0, 20.0)),
+    });
 
     world.add_hittable(Rc::new(Sphere::new(
         Point3::new(0.0, -100.5, -1.0),
@@ -39,12 +72,200 @@ fn main() {
         material_right,
     )));
 
+    let light = Rc::new(Sphere::new(
+        Point3::new(0.0, 1.5, -0.5),
+        0.2,
+        material_light,
+    ));
+    world.add_hittable(light.clone());
+    let mut lights = hittable::HittableObjects::new();
+    lights.add_hittable(light);
+
     let mut camera = ray_tracing::camera::Camera::default();
+    camera.progress = Box::new(TerminalProgress::new());
+    camera.collect_statistics = std::env::var_os("STATS").is_some();
 
     camera.img_width = 1280;
     camera.aspect_ratio = 16.0 / 9.0;
     camera.samples_per_pixel = 64;
-    camera.max_bounces = 32;
+    camera.integrator = match std::env::args().nth(1).as_deref() {
+        Some("normals") => Box::new(integrator::NormalsIntegrator),
+        Some("depth") => Box::new(integrator::DepthIntegrator::default()),
+        Some("ao") => Box::new(integrator::AmbientOcclusionIntegrator::default()),
+        Some("whitted") => Box::new(integrator::WhittedIntegrator::default()),
+        Some("spectral") => Box::new(integrator::SpectralPathIntegrator {
+            max_bounces: 32,
+            ..Default::default()
+        }),
+        _ => Box::new(integrator::PathIntegrator {
+            max_bounces: 32,
+            ..Default::default()
+        }),
+    };
+    let seed = rand::random();
+    camera.sampler = match std::env::args().nth(2).as_deref() {
+        Some("stratified") => Box::new(sampler::StratifiedSampler::new(8, 8, seed)),
+        Some("halton") => Box::new(sampler::HaltonSampler::new(seed)),
+        Some("sobol") => Box::new(sampler::SobolSampler::new(seed)),
+        _ => Box::new(sampler::IndependentSampler::new(seed)),
+    };
+
+    // Normalized "x_min,y_min,x_max,y_max" crop window
+    camera.crop_window = std::env::args().nth(3).map(|window| {
+        let bounds: Vec<f64> = window
+            .split(',')
+            .map(|bound| bound.trim().parse().expect("Crop bounds must be numbers"))
+            .collect();
+        assert!(bounds.len() == 4, "Crop window needs four bounds");
+        tile::CropWindow::Normalized {
+            x_min: bounds[0],
+            y_min: bounds[1],
+            x_max: bounds[2],
+            y_max: bounds[3],
+        }
+    });
+
+    // Rerunning the same command continues from the checkpoint, the scene is
+    // defined in this file so its source identifies the scene
+    if let Some(path) = std::env::var_os("CHECKPOINT") {
+        camera.scene_hash = scene_hash();
+        camera.checkpoint_path = Some(path.into());
+        camera.resume = true;
+    }
+
+    // Seconds to render for, keeping whatever was traced when time runs out
+    if let Some(seconds) = std::env::var_os("TIME_BUDGET") {
+        let budget = seconds
+            .to_str()
+            .and_then(|seconds| seconds.parse().ok())
+            .and_then(|seconds| std::time::Duration::try_from_secs_f64(seconds).ok());
+        match budget {
+            Some(budget) => camera.time_budget = Some(budget),
+            None => {
+                eprintln!("TIME_BUDGET must be a non negative number of seconds");
+                std::process::exit(2);
+            }
+        }
+    }
+
+    // With a previous render the crop window is composited into it instead of
+    // being written on its own
+    match std::env::args().nth(4) {
+        Some(path) => {
+            let mut file = std::fs::File::open(path).expect("Couldn't open the base image");
+            let mut buffer = image::AccumulationBuffer::read_ppm(&mut file).unwrap();
+            camera.render_into(&mut buffer, &world, &lights);
+            buffer
+                .write_ppm(&mut std::io::BufWriter::new(std::io::stdout()))
+                .unwrap();
+        }
+        None => camera.render(&world, &lights),
+    }
+}
+
+// Environment variables changing what's in the scene
+const SCENE_VARIABLES: [&str; 2] = ["METAL", "LIGHT_TEMPERATURE"];
+
+// Identifies the scene by this file's source, the arguments and the scene
+// variables. FNV-1a rather than std's hasher, whose output may change between
+// Rust releases and would orphan existing checkpoints.
+fn scene_hash() -> u64 {
+    const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const FNV_PRIME: u64 = 0x100000001b3;
+
+    let mut hash = FNV_OFFSET_BASIS;
+    let mut write = |bytes: &[u8]| {
+        // Length prefixed so neighbouring values can't run into each other
+        for byte in (bytes.len() as u64).to_le_bytes().iter().chain(bytes) {
+            hash = (hash ^ *byte as u64).wrapping_mul(FNV_PRIME);
+        }
+    };
+
+    write(include_str!("main.rs").as_bytes());
+    for arg in std::env::args_os().skip(1) {
+        write(arg.as_encoded_bytes());
+    }
+    for name in SCENE_VARIABLES {
+        write(name.as_bytes());
+        match std::env::var_os(name) {
+            Some(value) => {
+                write(b"set");
+                write(value.as_encoded_bytes());
+            }
+            None => write(b"unset"),
+        }
+    }
+    hash
+}
+
+// Single line progress bar on stderr
+struct TerminalProgress {
+    last_draw: Option<std::time::Instant>,
+}
+
+impl TerminalProgress {
+    const WIDTH: usize = 30;
+    const REDRAW_INTERVAL: std::time::Duration = std::time::Duration::from_millis(100);
+
+    fn new() -> Self {
+        Self { last_draw: None }
+    }
+}
+
+impl progress::ProgressObserver for TerminalProgress {
+    fn on_start(&mut self, width: usize, height: usize) {
+        eprintln!("Size: {width} x {height}");
+        self.last_draw = None;
+    }
+
+    fn on_progress(&mut self, progress: &progress::Progress) {
+        let done = progress.completed_units == progress.total_units;
+        if !done
+            && self
+                .last_draw
+                .is_some_and(|last_draw| last_draw.elapsed() < Self::REDRAW_INTERVAL)
+        {
+            return;
+        }
+        self.last_draw = Some(std::time::Instant::now());
+
+        let filled = (progress.fraction() * Self::WIDTH as f64) as usize;
+        let eta = match progress.eta() {
+            Some(eta) => format!("{:.1}s", eta.as_secs_f64()),
+            None => String::from("?"),
+        };
+        eprint!(
+            "\r[{}{}] {:5.1}% {:.1}s elapsed, ETA {eta}, {:.0} rays/s ",
+            "#".repeat(filled),
+            "-".repeat(Self::WIDTH - filled),
+            100.0 * progress.fraction(),
+            progress.elapsed.as_secs_f64(),
+            progress.rays_per_second()
+        );
+    }
+
+    fn on_finish(&mut self, stats: &progress::RenderStats) {
+        eprintln!(
+            "\n{} in {:.2}s, {} camera rays, {:.0} rays/s",
+            if stats.stopped_early {
+                "Stopped early"
+            } else {
+                "Done"
+            },
+            stats.elapsed.as_secs_f64(),
+            stats.camera_rays,
+            stats.rays_per_second()
+        );
+        if let Some(statistics) = &stats.statistics {
+            eprint!("{statistics}");
+        }
+    }
+
+    fn on_message(&mut self, message: &str) {
+        eprintln!("{message}");
+    }
 
-    camera.render(&world);
+    fn on_warning(&mut self, message: &str) {
+        eprintln!("{message}");
+    }
 }
@@ -1,44 +1,318 @@
-use rand::Rng;
+pub struct ScatterRecord {
+    pub attenuation: crate::vec3::Color,
+    // Distribution to sample the scattered direction from, unused for specular records
+    pub pdf: Option<Box<dyn crate::pdf::Pdf>>,
+    pub is_specular: bool,
+    pub specular_ray: crate::ray::Ray,
+}
+
+impl ScatterRecord {
+    pub fn new() -> Self {
+        Self {
+            attenuation: crate::vec3::Color::zeroed(),
+            pdf: None,
+            is_specular: false,
+            specular_ray: crate::ray::Ray::new(
+                crate::vec3::Point3::zeroed(),
+                crate::vec3::Vec3::zeroed(),
+            ),
+        }
+    }
+}
+
+impl Default for ScatterRecord {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 pub trait Material {
-    fn scatter<'a>(
+    fn scatter(
         &self,
-        _ray_in: &crate::ray::Ray,
-        hit_record: &'a mut crate::hittable::HitRecord,
-        attenuation: &mut crate::vec3::Color,
-        scattered: &mut crate::ray::Ray<'a>,
-        rng_gen: &mut rand::rngs::SmallRng,
+        ray_in: &crate::ray::Ray,
+        hit_record: &crate::hittable::HitRecord,
+        scatter_record: &mut ScatterRecord,
+        sampler: &mut dyn crate::sampler::Sampler,
     ) -> bool;
+
+    // BSDF times the cosine term for scattering towards `direction`. Only
+    // meaningful for non specular scatter records.
+    fn eval(
+        &self,
+        _ray_in: &crate::ray::Ray,
+        _hit_record: &crate::hittable::HitRecord,
+        _direction: &crate::vec3::Vec3,
+    ) -> crate::vec3::Color {
+        crate::vec3::Color::zeroed()
+    }
+
+    fn emitted(
+        &self,
+        _ray_in: &crate::ray::Ray,
+        _hit_record: &crate::hittable::HitRecord,
+    ) -> crate::vec3::Color {
+        crate::vec3::Color::zeroed()
+    }
+
+    // Emission at the wavelengths of a spectral path, upsampled from `emitted`
+    // unless the material knows its spectrum
+    fn emitted_spectrum(
+        &self,
+        ray_in: &crate::ray::Ray,
+        hit_record: &crate::hittable::HitRecord,
+        wavelengths: &crate::spectrum::SampledWavelengths,
+    ) -> crate::spectrum::SampledSpectrum {
+        crate::spectrum::SampledSpectrum::from_rgb(&self.emitted(ray_in, hit_record), wavelengths)
+    }
+
+    // Absorption per unit distance inside the material. Integrators attenuate
+    // every segment a path travels through it, whatever it hits next.
+    fn absorption(
+        &self,
+        _ray_in: &crate::ray::Ray,
+        _hit_record: &crate::hittable::HitRecord,
+    ) -> Option<crate::vec3::Color> {
+        None
+    }
+
+    // Whether scattering depends on the ray's wavelength, integrators then
+    // assign one to the path before calling `scatter`
+    fn is_dispersive(&self) -> bool {
+        false
+    }
+
+    // Used to tell materials apart in render statistics
+    fn name(&self) -> &'static str {
+        let type_name = std::any::type_name::<Self>();
+        type_name.rsplit("::").next().unwrap_or(type_name)
+    }
 }
 
 pub struct Metal {
     albedo: super::vec3::Color,
     fuzziness: f64,
+    // Film and the metal's IOR, estimated from the albedo
+    thin_film: Option<(crate::fresnel::ThinFilm, crate::fresnel::ComplexIor)>,
 }
 
 impl Metal {
     pub const fn new(albedo: super::vec3::Color, fuzziness: f64) -> Self {
-        Self { albedo, fuzziness }
+        Self {
+            albedo,
+            fuzziness,
+            thin_film: None,
+        }
+    }
+
+    // Iridescent metal, like an oil slick or heat tinted steel
+    pub fn with_thin_film(mut self, film: crate::fresnel::ThinFilm) -> Self {
+        let ior = crate::fresnel::ComplexIor::from_reflectance(&self.albedo, &self.albedo);
+        self.thin_film = Some((film, ior));
+        self
+    }
+
+    // The albedo, times how much the film changes the metal's own reflectance
+    fn reflectance(
+        &self,
+        ray_in: &crate::ray::Ray,
+        hit_record: &crate::hittable::HitRecord,
+    ) -> super::vec3::Color {
+        let Some((film, ior)) = &self.thin_film else {
+            return self.albedo.clone();
+        };
+        let cos_theta = (-ray_in.dir().unit_vector()).dot(&hit_record.normal);
+        let with_film = film.reflectance_rgb(cos_theta, 1.0, ior, ray_in.wavelength());
+        let bare = crate::fresnel::ConductorFresnel::Complex(ior.clone()).evaluate(cos_theta);
+        let channel = |index: usize| {
+            if bare[index] <= 0.0 {
+                return 0.0;
+            }
+            self.albedo[index] * with_film[index] / bare[index]
+        };
+
+        super::vec3::Color::new(channel(0), channel(1), channel(2))
     }
 }
 
 impl Material for Metal {
-    fn scatter<'a>(
+    fn scatter(
         &self,
         ray_in: &crate::ray::Ray,
-        hit_record: &'a mut crate::hittable::HitRecord,
-        attenuation: &mut crate::vec3::Color,
-        scattered: &mut crate::ray::Ray<'a>,
-        rng_gen: &mut rand::rngs::SmallRng,
+        hit_record: &crate::hittable::HitRecord,
+        scatter_record: &mut ScatterRecord,
+        _sampler: &mut dyn crate::sampler::Sampler,
     ) -> bool {
         let reflected = super::vec3::Vec3::reflect(&ray_in.dir().unit_vector(), &hit_record.normal);
-        scattered.set_origin(&hit_record.point);
-        scattered
-            .set_dir(reflected + self.fuzziness * crate::vec3::Vec3::random_unit_vector(rng_gen));
+        scatter_record.attenuation = self.reflectance(ray_in, hit_record);
+
+        if self.fuzziness <= 0.0 {
+            scatter_record.pdf = None;
+            scatter_record.is_specular = true;
+            scatter_record.specular_ray = crate::ray::Ray::new(hit_record.point.clone(), reflected);
+        } else {
+            scatter_record.pdf = Some(Box::new(crate::pdf::FuzzyReflectionPdf::new(
+                &reflected,
+                self.fuzziness,
+            )));
+            scatter_record.is_specular = false;
+        }
+
+        true
+    }
+
+    fn eval(
+        &self,
+        ray_in: &crate::ray::Ray,
+        hit_record: &crate::hittable::HitRecord,
+        direction: &crate::vec3::Vec3,
+    ) -> crate::vec3::Color {
+        use crate::pdf::Pdf;
+
+        // Directions perturbed below the surface are absorbed
+        if direction.dot(&hit_record.normal) <= 0.0 || self.fuzziness <= 0.0 {
+            return crate::vec3::Color::zeroed();
+        }
+        let reflected = super::vec3::Vec3::reflect(&ray_in.dir().unit_vector(), &hit_record.normal);
+        let pdf = crate::pdf::FuzzyReflectionPdf::new(&reflected, self.fuzziness);
+
+        pdf.value(direction) * self.reflectance(ray_in, hit_record)
+    }
+
+    // Interference depends on the wavelength
+    fn is_dispersive(&self) -> bool {
+        self.thin_film.is_some()
+    }
+}
+
+// Rough metal with a GGX microfacet distribution. Anisotropic roughness
+// stretches highlights along a world space tangent, `roughness_u` applying
+// along it and `roughness_v` across it.
+pub struct Conductor {
+    fresnel: crate::fresnel::ConductorFresnel,
+    distribution: crate::microfacet::TrowbridgeReitz,
+    // Only set for anisotropic conductors, isotropic ones look the same in any frame
+    tangent: Option<super::vec3::Vec3>,
+}
+
+impl Conductor {
+    // `albedo` is the reflectance at normal incidence
+    pub fn new(albedo: super::vec3::Color, roughness: f64) -> Self {
+        Self::with_fresnel(crate::fresnel::ConductorFresnel::Schlick(albedo), roughness)
+    }
+
+    // `tangent` is projected onto the surface at every hit, e.g. the axis a
+    // cylinder was brushed around
+    pub fn anisotropic(
+        albedo: super::vec3::Color,
+        roughness_u: f64,
+        roughness_v: f64,
+        tangent: super::vec3::Vec3,
+    ) -> Self {
+        Self::anisotropic_with_fresnel(
+            crate::fresnel::ConductorFresnel::Schlick(albedo),
+            roughness_u,
+            roughness_v,
+            tangent,
+        )
+    }
+
+    pub fn with_ior(ior: crate::fresnel::ComplexIor, roughness: f64) -> Self {
+        Self::with_fresnel(crate::fresnel::ConductorFresnel::Complex(ior), roughness)
+    }
+
+    // One of `ComplexIor::PRESET_NAMES`, None for unknown metals
+    pub fn preset(name: &str, roughness: f64) -> Option<Self> {
+        crate::fresnel::ComplexIor::preset(name).map(|ior| Self::with_ior(ior, roughness))
+    }
+
+    pub fn with_fresnel(fresnel: crate::fresnel::ConductorFresnel, roughness: f64) -> Self {
+        use crate::microfacet::TrowbridgeReitz;
+
+        let alpha = TrowbridgeReitz::roughness_to_alpha(roughness);
+        Self {
+            fresnel,
+            distribution: TrowbridgeReitz::new(alpha, alpha),
+            tangent: None,
+        }
+    }
+
+    pub fn anisotropic_with_fresnel(
+        fresnel: crate::fresnel::ConductorFresnel,
+        roughness_u: f64,
+        roughness_v: f64,
+        tangent: super::vec3::Vec3,
+    ) -> Self {
+        use crate::microfacet::TrowbridgeReitz;
+
+        Self {
+            fresnel,
+            distribution: TrowbridgeReitz::new(
+                TrowbridgeReitz::roughness_to_alpha(roughness_u),
+                TrowbridgeReitz::roughness_to_alpha(roughness_v),
+            ),
+            tangent: Some(tangent),
+        }
+    }
+
+    // Shading frame shared by `eval` and the sampling pdf
+    fn frame(&self, normal: &super::vec3::Vec3) -> crate::onb::Onb {
+        match &self.tangent {
+            Some(tangent) => crate::onb::Onb::from_normal_and_tangent(normal, tangent),
+            None => crate::onb::Onb::new(normal),
+        }
+    }
+}
+
+impl Material for Conductor {
+    fn scatter(
+        &self,
+        ray_in: &crate::ray::Ray,
+        hit_record: &crate::hittable::HitRecord,
+        scatter_record: &mut ScatterRecord,
+        _sampler: &mut dyn crate::sampler::Sampler,
+    ) -> bool {
+        let unit_direction = ray_in.dir().unit_vector();
+
+        if self.distribution.effectively_smooth() {
+            let cosine = (-&unit_direction).dot(&hit_record.normal);
+            scatter_record.attenuation = self.fresnel.evaluate(cosine);
+            scatter_record.pdf = None;
+            scatter_record.is_specular = true;
+            scatter_record.specular_ray = crate::ray::Ray::new(
+                hit_record.point.clone(),
+                super::vec3::Vec3::reflect(&unit_direction, &hit_record.normal),
+            );
+        } else {
+            scatter_record.attenuation = self.fresnel.evaluate(1.0);
+            scatter_record.pdf = Some(Box::new(crate::pdf::MicrofacetReflectionPdf::with_frame(
+                self.frame(&hit_record.normal),
+                &-unit_direction,
+                self.distribution,
+            )));
+            scatter_record.is_specular = false;
+        }
+
+        true
+    }
 
-        *attenuation = self.albedo.clone();
+    fn eval(
+        &self,
+        ray_in: &crate::ray::Ray,
+        hit_record: &crate::hittable::HitRecord,
+        direction: &crate::vec3::Vec3,
+    ) -> crate::vec3::Color {
+        let frame = self.frame(&hit_record.normal);
+        let wo = frame.to_local(&-ray_in.dir().unit_vector());
+        let wi = frame.to_local(&direction.unit_vector());
+        let half_vector = &wo + &wi;
+        if wo.z() <= 0.0 || wi.z() <= 0.0 || half_vector.near_zero() {
+            return crate::vec3::Color::zeroed();
+        }
+        let wm = half_vector.unit_vector();
 
-        scattered.dir().dot(&hit_record.normal) > 0.0
+        // D * G * F / (4 cos_o cos_i), times the cosine term
+        let specular = self.distribution.d(&wm) * self.distribution.g(&wo, &wi) / (4.0 * wo.z());
+        specular * self.fresnel.evaluate(wo.dot(&wm))
     }
 }
 
@@ -53,61 +327,171 @@ impl Lambertian {
 }
 
 impl Material for Lambertian {
-    fn scatter<'a>(
+    fn scatter(
         &self,
         _ray_in: &crate::ray::Ray,
-        hit_record: &'a mut crate::hittable::HitRecord,
-        attenuation: &mut crate::vec3::Color,
-        scattered: &mut crate::ray::Ray<'a>,
-        rng_gen: &mut rand::rngs::SmallRng,
+        hit_record: &crate::hittable::HitRecord,
+        scatter_record: &mut ScatterRecord,
+        _sampler: &mut dyn crate::sampler::Sampler,
     ) -> bool {
-        let mut scatter_direction =
-            &hit_record.normal + &super::vec3::Vec3::random_unit_vector(rng_gen);
-        if scatter_direction.near_zero() {
-            scatter_direction = hit_record.normal.clone();
+        scatter_record.attenuation = self.albedo.clone();
+        scatter_record.pdf = Some(Box::new(crate::pdf::CosinePdf::new(&hit_record.normal)));
+        scatter_record.is_specular = false;
+
+        true
+    }
+
+    fn eval(
+        &self,
+        _ray_in: &crate::ray::Ray,
+        hit_record: &crate::hittable::HitRecord,
+        direction: &crate::vec3::Vec3,
+    ) -> crate::vec3::Color {
+        let cosine = hit_record.normal.dot(&direction.unit_vector());
+
+        f64::max(0.0, cosine / crate::consts::PI) * &self.albedo
+    }
+}
+
+// Rough diffuse surface made of V-shaped Lambertian microfacets (Oren and
+// Nayar 1994), backscattering towards the light like clay or cloth. `sigma`
+// is the standard deviation of the facet angles in degrees, 0 being Lambertian.
+pub struct OrenNayar {
+    albedo: super::vec3::Color,
+    a: f64,
+    b: f64,
+}
+
+impl OrenNayar {
+    pub fn new(albedo: super::vec3::Color, sigma: f64) -> Self {
+        let sigma = crate::degrees_to_radians(sigma);
+        let sigma_squared = sigma * sigma;
+        Self {
+            albedo,
+            a: 1.0 - sigma_squared / (2.0 * (sigma_squared + 0.33)),
+            b: 0.45 * sigma_squared / (sigma_squared + 0.09),
         }
-        // let p_ref = unsafe { &*(&hit_record.point as *const crate::vec3::Vec3)};
-        scattered.set_origin(&hit_record.point);
-        scattered.set_dir(scatter_direction);
+    }
+}
 
-        *attenuation = self.albedo.clone();
+impl Material for OrenNayar {
+    fn scatter(
+        &self,
+        _ray_in: &crate::ray::Ray,
+        hit_record: &crate::hittable::HitRecord,
+        scatter_record: &mut ScatterRecord,
+        _sampler: &mut dyn crate::sampler::Sampler,
+    ) -> bool {
+        scatter_record.attenuation = self.albedo.clone();
+        scatter_record.pdf = Some(Box::new(crate::pdf::CosinePdf::new(&hit_record.normal)));
+        scatter_record.is_specular = false;
 
         true
     }
+
+    fn eval(
+        &self,
+        ray_in: &crate::ray::Ray,
+        hit_record: &crate::hittable::HitRecord,
+        direction: &crate::vec3::Vec3,
+    ) -> crate::vec3::Color {
+        let frame = crate::onb::Onb::new(&hit_record.normal);
+        let wo = frame.to_local(&-ray_in.dir().unit_vector());
+        let wi = frame.to_local(&direction.unit_vector());
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return crate::vec3::Color::zeroed();
+        }
+
+        let sin_i = f64::sqrt(f64::max(0.0, 1.0 - wi.z() * wi.z()));
+        let sin_o = f64::sqrt(f64::max(0.0, 1.0 - wo.z() * wo.z()));
+        // Cosine of the azimuth between the directions, times sin(alpha) tan(beta)
+        // with alpha the larger and beta the smaller of the polar angles
+        let retro_reflection = if sin_i > 1e-4 && sin_o > 1e-4 {
+            let cos_phi = f64::max(0.0, (wi.x() * wo.x() + wi.y() * wo.y()) / (sin_i * sin_o));
+            let (sin_alpha, tan_beta) = if wi.z() > wo.z() {
+                (sin_o, sin_i / wi.z())
+            } else {
+                (sin_i, sin_o / wo.z())
+            };
+            cos_phi * sin_alpha * tan_beta
+        } else {
+            0.0
+        };
+
+        (self.a + self.b * retro_reflection) * wi.z() / crate::consts::PI * &self.albedo
+    }
 }
 
 pub struct Dieletric {
     index_of_refraction: f64,
+    // Per unit distance traveled inside, see `absorption_from_tint`
+    absorption: super::vec3::Color,
+    dispersion: Option<crate::spectrum::Dispersion>,
+    // Coating the outside of the surface
+    thin_film: Option<crate::fresnel::ThinFilm>,
 }
 
 impl Dieletric {
     pub fn new(index_of_refraction: f64) -> Self {
+        Self::with_absorption(index_of_refraction, super::vec3::Color::zeroed())
+    }
+
+    pub fn with_absorption(index_of_refraction: f64, absorption: super::vec3::Color) -> Self {
         Self {
             index_of_refraction,
+            absorption,
+            dispersion: None,
+            thin_film: None,
         }
     }
 
+    // Rays without a wavelength see the IOR at `spectrum::REFERENCE_WAVELENGTH`
+    pub fn dispersive(
+        dispersion: crate::spectrum::Dispersion,
+        absorption: super::vec3::Color,
+    ) -> Self {
+        Self {
+            index_of_refraction: dispersion
+                .index_of_refraction(crate::spectrum::REFERENCE_WAVELENGTH),
+            absorption,
+            dispersion: Some(dispersion),
+            thin_film: None,
+        }
+    }
+
+    // Iridescent surface, like a soap bubble with an IOR of 1
+    pub fn with_thin_film(mut self, film: crate::fresnel::ThinFilm) -> Self {
+        self.thin_film = Some(film);
+        self
+    }
+
+    fn index_of_refraction(&self, ray: &crate::ray::Ray) -> f64 {
+        dispersed_ior(self.index_of_refraction, self.dispersion.as_ref(), ray)
+    }
+
     pub fn reflectance(cosine: f64, index_of_refraction: f64) -> f64 {
-        let mut r0 = (1.0-index_of_refraction)/(1.0+index_of_refraction);
+        let mut r0 = (1.0 - index_of_refraction) / (1.0 + index_of_refraction);
         r0 *= r0;
-        r0 + (1.0-r0)*f64::powi(1.0-cosine, 5)
+        r0 + (1.0 - r0) * f64::powi(1.0 - cosine, 5)
     }
 }
 
 impl Material for Dieletric {
-    fn scatter<'a>(
+    fn scatter(
         &self,
         ray_in: &crate::ray::Ray,
-        hit_record: &'a mut crate::hittable::HitRecord,
-        attenuation: &mut crate::vec3::Color,
-        scattered: &mut crate::ray::Ray<'a>,
-        rng_gen: &mut rand::rngs::SmallRng,
+        hit_record: &crate::hittable::HitRecord,
+        scatter_record: &mut ScatterRecord,
+        sampler: &mut dyn crate::sampler::Sampler,
     ) -> bool {
-        *attenuation = super::vec3::Color::new(1.0, 1.0, 1.0);
+        scatter_record.attenuation = super::vec3::Color::new(1.0, 1.0, 1.0);
+        scatter_record.pdf = None;
+        scatter_record.is_specular = true;
+        let index_of_refraction = self.index_of_refraction(ray_in);
         let refraction_ratio = if hit_record.front_face {
-            1.0 / self.index_of_refraction
+            1.0 / index_of_refraction
         } else {
-            self.index_of_refraction
+            index_of_refraction
         };
 
         let unit_direction = ray_in.dir().unit_vector();
@@ -115,15 +499,842 @@ impl Material for Dieletric {
         let sin_theta = f64::sqrt(1.0 - cos_theta * cos_theta);
 
         let cannot_refract = refraction_ratio * sin_theta > 1.0;
-        let direction = if cannot_refract || Self::reflectance(cos_theta, refraction_ratio) > rng_gen.gen::<f64>(){
+        let reflects = if cannot_refract {
+            true
+        } else if let Some(film) = &self.thin_film {
+            // Reflect as often as the film does on average, weighting each
+            // channel by how much it actually reflects
+            let (outside, inside) = if hit_record.front_face {
+                (1.0, index_of_refraction)
+            } else {
+                (index_of_refraction, 1.0)
+            };
+            let reflectance = film.reflectance_rgb(
+                cos_theta,
+                outside,
+                &crate::fresnel::ComplexIor::dielectric(inside),
+                ray_in.wavelength(),
+            );
+            let probability = f64::clamp(
+                (reflectance.x() + reflectance.y() + reflectance.z()) / 3.0,
+                1e-3,
+                1.0 - 1e-3,
+            );
+            let reflects = sampler.get_1d() < probability;
+            scatter_record.attenuation = if reflects {
+                scatter_record.attenuation.clone() * reflectance / probability
+            } else {
+                scatter_record.attenuation.clone()
+                    * (super::vec3::Color::new(1.0, 1.0, 1.0) - reflectance)
+                    / (1.0 - probability)
+            };
+            reflects
+        } else {
+            Self::reflectance(cos_theta, refraction_ratio) > sampler.get_1d()
+        };
+        let direction = if reflects {
             super::vec3::Vec3::reflect(&unit_direction, &hit_record.normal)
         } else {
             super::vec3::Vec3::refract(&unit_direction, &hit_record.normal, refraction_ratio)
         };
 
-        scattered.set_origin(&hit_record.point);
-        scattered.set_dir(direction);
+        scatter_record.specular_ray = crate::ray::Ray::new(hit_record.point.clone(), direction);
 
         true
     }
+
+    fn absorption(
+        &self,
+        _ray_in: &crate::ray::Ray,
+        _hit_record: &crate::hittable::HitRecord,
+    ) -> Option<crate::vec3::Color> {
+        (!self.absorption.near_zero()).then(|| self.absorption.clone())
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.dispersion.is_some() || self.thin_film.is_some()
+    }
+}
+
+// Frosted glass, a GGX microfacet interface both reflecting and refracting
+// with the exact dielectric Fresnel term. Like `Dieletric`, radiance isn't
+// scaled by the squared IOR ratio on refraction.
+pub struct RoughDielectric {
+    index_of_refraction: f64,
+    distribution: crate::microfacet::TrowbridgeReitz,
+    absorption: super::vec3::Color,
+    dispersion: Option<crate::spectrum::Dispersion>,
+}
+
+impl RoughDielectric {
+    pub fn new(index_of_refraction: f64, roughness: f64) -> Self {
+        Self::with_absorption(index_of_refraction, roughness, super::vec3::Color::zeroed())
+    }
+
+    pub fn with_absorption(
+        index_of_refraction: f64,
+        roughness: f64,
+        absorption: super::vec3::Color,
+    ) -> Self {
+        use crate::microfacet::TrowbridgeReitz;
+
+        let alpha = TrowbridgeReitz::roughness_to_alpha(roughness);
+        Self {
+            index_of_refraction,
+            distribution: TrowbridgeReitz::new(alpha, alpha),
+            absorption,
+            dispersion: None,
+        }
+    }
+
+    pub fn dispersive(
+        dispersion: crate::spectrum::Dispersion,
+        roughness: f64,
+        absorption: super::vec3::Color,
+    ) -> Self {
+        let mut material = Self::with_absorption(
+            dispersion.index_of_refraction(crate::spectrum::REFERENCE_WAVELENGTH),
+            roughness,
+            absorption,
+        );
+        material.dispersion = Some(dispersion);
+        material
+    }
+
+    // IOR below the hit normal over the IOR above it
+    fn relative_ior(&self, ray: &crate::ray::Ray, hit_record: &crate::hittable::HitRecord) -> f64 {
+        let index_of_refraction =
+            dispersed_ior(self.index_of_refraction, self.dispersion.as_ref(), ray);
+        if hit_record.front_face {
+            index_of_refraction
+        } else {
+            1.0 / index_of_refraction
+        }
+    }
+}
+
+impl Material for RoughDielectric {
+    fn scatter(
+        &self,
+        ray_in: &crate::ray::Ray,
+        hit_record: &crate::hittable::HitRecord,
+        scatter_record: &mut ScatterRecord,
+        sampler: &mut dyn crate::sampler::Sampler,
+    ) -> bool {
+        let eta = self.relative_ior(ray_in, hit_record);
+        let wo = -ray_in.dir().unit_vector();
+        scatter_record.attenuation = super::vec3::Color::new(1.0, 1.0, 1.0);
+
+        if self.distribution.effectively_smooth() {
+            let reflectance = crate::fresnel::dielectric(wo.dot(&hit_record.normal), eta);
+            let choice = sampler.get_1d();
+            let direction = match crate::microfacet::refract(&wo, &hit_record.normal, eta) {
+                Some(refracted) if choice >= reflectance => refracted,
+                _ => super::vec3::Vec3::reflect(&-wo, &hit_record.normal),
+            };
+
+            scatter_record.pdf = None;
+            scatter_record.is_specular = true;
+            scatter_record.specular_ray = crate::ray::Ray::new(hit_record.point.clone(), direction);
+        } else {
+            scatter_record.pdf = Some(Box::new(crate::pdf::MicrofacetDielectricPdf::new(
+                &hit_record.normal,
+                &wo,
+                eta,
+                self.distribution,
+            )));
+            scatter_record.is_specular = false;
+        }
+
+        true
+    }
+
+    fn eval(
+        &self,
+        ray_in: &crate::ray::Ray,
+        hit_record: &crate::hittable::HitRecord,
+        direction: &crate::vec3::Vec3,
+    ) -> crate::vec3::Color {
+        let eta = self.relative_ior(ray_in, hit_record);
+        let frame = crate::onb::Onb::new(&hit_record.normal);
+        let wo = frame.to_local(&-ray_in.dir().unit_vector());
+        let wi = frame.to_local(&direction.unit_vector());
+
+        let value = microfacet_dielectric(&self.distribution, &wo, &wi, eta);
+        super::vec3::Color::new(value, value, value)
+    }
+
+    fn absorption(
+        &self,
+        _ray_in: &crate::ray::Ray,
+        _hit_record: &crate::hittable::HitRecord,
+    ) -> Option<crate::vec3::Color> {
+        (!self.absorption.near_zero()).then(|| self.absorption.clone())
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.dispersion.is_some()
+    }
+}
+
+// Principled uber material after Disney's (Burley 2012), the parameters
+// artists author for PBR pipelines. A Burley diffuse base with sheen, a GGX
+// specular lobe blending from dielectric to metal, a clearcoat on top and
+// rough dielectric transmission. Everything but `ior` is in [0, 1].
+pub struct Principled {
+    pub base_color: super::vec3::Color,
+    pub metallic: f64,
+    pub roughness: f64,
+    // Reflectance of the dielectric base at normal incidence, 0.5 being 4%
+    pub specular: f64,
+    pub sheen: f64,
+    // Shifts the sheen from white to the hue of `base_color`
+    pub sheen_tint: f64,
+    pub clearcoat: f64,
+    pub clearcoat_roughness: f64,
+    pub transmission: f64,
+    // Only used by transmission, reflections follow `specular`
+    pub ior: f64,
+}
+
+impl Principled {
+    pub fn new(base_color: super::vec3::Color) -> Self {
+        Self {
+            base_color,
+            ..Default::default()
+        }
+    }
+
+    // Delta lobes can't be mixed with the others, so even a roughness of 0
+    // keeps a tiny spread
+    fn distribution(roughness: f64) -> crate::microfacet::TrowbridgeReitz {
+        let alpha = f64::max(
+            crate::microfacet::TrowbridgeReitz::roughness_to_alpha(roughness),
+            1e-3,
+        );
+        crate::microfacet::TrowbridgeReitz::new(alpha, alpha)
+    }
+
+    fn specular_f0(&self) -> super::vec3::Color {
+        let dielectric = 0.08 * self.specular;
+        crate::lerp(
+            self.metallic,
+            super::vec3::Color::new(dielectric, dielectric, dielectric),
+            self.base_color.clone(),
+        )
+    }
+
+    // Weights of the diffuse, specular and transmission lobes
+    fn lobe_weights(&self) -> (f64, f64, f64) {
+        let dielectric = 1.0 - self.metallic;
+        (
+            dielectric * (1.0 - self.transmission),
+            1.0 - dielectric * self.transmission,
+            dielectric * self.transmission,
+        )
+    }
+
+    // Whether a hit travels inside the object. Only transmission lets light
+    // in, opaque surfaces seen from behind shade like their front.
+    fn inside(&self, front_face: bool) -> bool {
+        let (_, _, transmission) = self.lobe_weights();
+        !front_face && transmission > 0.0
+    }
+
+    fn sheen_color(&self) -> super::vec3::Color {
+        let luminance = self.base_color.luminance();
+        let tint = if luminance > 0.0 {
+            &self.base_color / luminance
+        } else {
+            super::vec3::Color::new(1.0, 1.0, 1.0)
+        };
+        self.sheen
+            * crate::lerp(
+                self.sheen_tint,
+                super::vec3::Color::new(1.0, 1.0, 1.0),
+                tint,
+            )
+    }
+
+    // Probabilities of sampling each lobe, roughly following how much they reflect
+    fn lobe_pdf(
+        &self,
+        normal: &super::vec3::Vec3,
+        wo: &super::vec3::Vec3,
+        front_face: bool,
+    ) -> crate::pdf::WeightedPdf {
+        let distribution = Self::distribution(self.roughness);
+        let mut pdf = crate::pdf::WeightedPdf::new();
+        if self.inside(front_face) {
+            // Only light refracted into the object travels inside of it
+            pdf.add(
+                1.0,
+                Box::new(crate::pdf::MicrofacetDielectricPdf::new(
+                    normal,
+                    wo,
+                    1.0 / self.ior,
+                    distribution,
+                )),
+            );
+            return pdf;
+        }
+
+        let cos_theta = wo.unit_vector().dot(normal);
+        let (diffuse, specular, transmission) = self.lobe_weights();
+        let fresnel = crate::fresnel::ConductorFresnel::Schlick(self.specular_f0());
+        pdf.add(diffuse, Box::new(crate::pdf::CosinePdf::new(normal)));
+        pdf.add(
+            specular * f64::max(fresnel.evaluate(cos_theta).luminance(), 0.1),
+            Box::new(crate::pdf::MicrofacetReflectionPdf::new(
+                normal,
+                wo,
+                distribution,
+            )),
+        );
+        pdf.add(
+            0.25 * self.clearcoat * crate::fresnel::dielectric(cos_theta, 1.5),
+            Box::new(crate::pdf::MicrofacetReflectionPdf::new(
+                normal,
+                wo,
+                Self::distribution(self.clearcoat_roughness),
+            )),
+        );
+        pdf.add(
+            transmission,
+            Box::new(crate::pdf::MicrofacetDielectricPdf::new(
+                normal,
+                wo,
+                self.ior,
+                distribution,
+            )),
+        );
+        pdf
+    }
+}
+
+impl Default for Principled {
+    fn default() -> Self {
+        Self {
+            base_color: super::vec3::Color::new(0.8, 0.8, 0.8),
+            metallic: 0.0,
+            roughness: 0.5,
+            specular: 0.5,
+            sheen: 0.0,
+            sheen_tint: 0.5,
+            clearcoat: 0.0,
+            clearcoat_roughness: 0.03,
+            transmission: 0.0,
+            ior: 1.5,
+        }
+    }
+}
+
+impl Material for Principled {
+    fn scatter(
+        &self,
+        ray_in: &crate::ray::Ray,
+        hit_record: &crate::hittable::HitRecord,
+        scatter_record: &mut ScatterRecord,
+        _sampler: &mut dyn crate::sampler::Sampler,
+    ) -> bool {
+        scatter_record.attenuation = self.base_color.clone();
+        scatter_record.pdf = Some(Box::new(self.lobe_pdf(
+            &hit_record.normal,
+            &-ray_in.dir().unit_vector(),
+            hit_record.front_face,
+        )));
+        scatter_record.is_specular = false;
+
+        true
+    }
+
+    fn eval(
+        &self,
+        ray_in: &crate::ray::Ray,
+        hit_record: &crate::hittable::HitRecord,
+        direction: &crate::vec3::Vec3,
+    ) -> crate::vec3::Color {
+        let frame = crate::onb::Onb::new(&hit_record.normal);
+        let wo = frame.to_local(&-ray_in.dir().unit_vector());
+        let wi = frame.to_local(&direction.unit_vector());
+        let distribution = Self::distribution(self.roughness);
+        if self.inside(hit_record.front_face) {
+            let value = microfacet_dielectric(&distribution, &wo, &wi, 1.0 / self.ior);
+            return super::vec3::Color::new(value, value, value);
+        }
+
+        let (diffuse, specular, transmission) = self.lobe_weights();
+        let mut value = super::vec3::Color::zeroed();
+        if wo.z() > 0.0 && wi.z() > 0.0 {
+            let wm = (&wo + &wi).unit_vector();
+            let cos_d = wi.dot(&wm);
+            let schlick_weight = |cosine: f64| f64::powi(1.0 - f64::clamp(cosine, 0.0, 1.0), 5);
+
+            // Burley diffuse, brightening towards grazing angles on rough surfaces
+            let grazing = 0.5 + 2.0 * self.roughness * cos_d * cos_d;
+            let retro_reflection = (1.0 + (grazing - 1.0) * schlick_weight(wi.z()))
+                * (1.0 + (grazing - 1.0) * schlick_weight(wo.z()));
+            let base = retro_reflection / crate::consts::PI * &self.base_color;
+            value += diffuse * wi.z() * (base + schlick_weight(cos_d) * self.sheen_color());
+
+            let fresnel = crate::fresnel::ConductorFresnel::Schlick(self.specular_f0());
+            value += specular * distribution.d(&wm) * distribution.g(&wo, &wi) / (4.0 * wo.z())
+                * fresnel.evaluate(wo.dot(&wm));
+
+            let coat = Self::distribution(self.clearcoat_roughness);
+            let coat_value = 0.25
+                * self.clearcoat
+                * coat.d(&wm)
+                * coat.g(&wo, &wi)
+                * crate::fresnel::dielectric(wo.dot(&wm), 1.5)
+                / (4.0 * wo.z());
+            value += super::vec3::Color::new(coat_value, coat_value, coat_value);
+        }
+        if transmission > 0.0 {
+            let lobe = transmission * microfacet_dielectric(&distribution, &wo, &wi, self.ior);
+            // Light refracted into the object takes on its color
+            value += if wi.z() < 0.0 {
+                lobe * &self.base_color
+            } else {
+                super::vec3::Color::new(lobe, lobe, lobe)
+            };
+        }
+
+        value
+    }
+}
+
+// Picks `second` with a chance of `weight`, `first` otherwise. The choice is a
+// hash of the ray and the hit so `scatter` and `eval` agree on it, each path
+// then sees just one of the materials, which works for specular ones too.
+pub struct Mix {
+    first: std::rc::Rc<dyn Material>,
+    second: std::rc::Rc<dyn Material>,
+    weight: std::rc::Rc<dyn crate::texture::Texture>,
+    salt: u64,
+}
+
+impl Mix {
+    pub fn new(
+        first: std::rc::Rc<dyn Material>,
+        second: std::rc::Rc<dyn Material>,
+        weight: f64,
+    ) -> Self {
+        Self::textured(
+            first,
+            second,
+            std::rc::Rc::new(crate::texture::SolidColor::new(super::vec3::Color::new(
+                weight, weight, weight,
+            ))),
+        )
+    }
+
+    // The weight is the luminance of the texture
+    pub fn textured(
+        first: std::rc::Rc<dyn Material>,
+        second: std::rc::Rc<dyn Material>,
+        weight: std::rc::Rc<dyn crate::texture::Texture>,
+    ) -> Self {
+        Self {
+            first,
+            second,
+            weight,
+            salt: next_salt(),
+        }
+    }
+
+    fn weight(&self, hit_record: &crate::hittable::HitRecord) -> f64 {
+        f64::clamp(self.weight.value(&hit_record.point).luminance(), 0.0, 1.0)
+    }
+
+    fn choose(
+        &self,
+        ray_in: &crate::ray::Ray,
+        hit_record: &crate::hittable::HitRecord,
+    ) -> &dyn Material {
+        if hashed_sample(self.salt, ray_in, hit_record) < self.weight(hit_record) {
+            self.second.as_ref()
+        } else {
+            self.first.as_ref()
+        }
+    }
+}
+
+impl Material for Mix {
+    fn scatter(
+        &self,
+        ray_in: &crate::ray::Ray,
+        hit_record: &crate::hittable::HitRecord,
+        scatter_record: &mut ScatterRecord,
+        sampler: &mut dyn crate::sampler::Sampler,
+    ) -> bool {
+        self.choose(ray_in, hit_record)
+            .scatter(ray_in, hit_record, scatter_record, sampler)
+    }
+
+    fn eval(
+        &self,
+        ray_in: &crate::ray::Ray,
+        hit_record: &crate::hittable::HitRecord,
+        direction: &crate::vec3::Vec3,
+    ) -> crate::vec3::Color {
+        self.choose(ray_in, hit_record)
+            .eval(ray_in, hit_record, direction)
+    }
+
+    fn emitted(
+        &self,
+        ray_in: &crate::ray::Ray,
+        hit_record: &crate::hittable::HitRecord,
+    ) -> crate::vec3::Color {
+        crate::lerp(
+            self.weight(hit_record),
+            self.first.emitted(ray_in, hit_record),
+            self.second.emitted(ray_in, hit_record),
+        )
+    }
+
+    fn emitted_spectrum(
+        &self,
+        ray_in: &crate::ray::Ray,
+        hit_record: &crate::hittable::HitRecord,
+        wavelengths: &crate::spectrum::SampledWavelengths,
+    ) -> crate::spectrum::SampledSpectrum {
+        let weight = self.weight(hit_record);
+        self.first.emitted_spectrum(ray_in, hit_record, wavelengths) * (1.0 - weight)
+            + self
+                .second
+                .emitted_spectrum(ray_in, hit_record, wavelengths)
+                * weight
+    }
+
+    fn absorption(
+        &self,
+        ray_in: &crate::ray::Ray,
+        hit_record: &crate::hittable::HitRecord,
+    ) -> Option<crate::vec3::Color> {
+        self.choose(ray_in, hit_record)
+            .absorption(ray_in, hit_record)
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.first.is_dispersive() || self.second.is_dispersive()
+    }
+}
+
+// Dielectric coating over any base material, like varnish on wood or the
+// clear coat of car paint. Light is either reflected by the coat, chosen in
+// proportion to its Fresnel reflectance the same way `Mix` chooses, or goes
+// through it to the base and back out, tinted by the coat on the way.
+pub struct Layered {
+    base: std::rc::Rc<dyn Material>,
+    index_of_refraction: f64,
+    distribution: crate::microfacet::TrowbridgeReitz,
+    // What's left of the light after crossing the coat at normal incidence, in and out
+    tint: super::vec3::Color,
+    salt: u64,
+}
+
+impl Layered {
+    pub fn new(base: std::rc::Rc<dyn Material>, index_of_refraction: f64, roughness: f64) -> Self {
+        Self::with_tint(
+            base,
+            index_of_refraction,
+            roughness,
+            super::vec3::Color::new(1.0, 1.0, 1.0),
+        )
+    }
+
+    pub fn with_tint(
+        base: std::rc::Rc<dyn Material>,
+        index_of_refraction: f64,
+        roughness: f64,
+        tint: super::vec3::Color,
+    ) -> Self {
+        use crate::microfacet::TrowbridgeReitz;
+
+        let alpha = TrowbridgeReitz::roughness_to_alpha(roughness);
+        Self {
+            base,
+            index_of_refraction,
+            distribution: TrowbridgeReitz::new(alpha, alpha),
+            tint,
+            salt: next_salt(),
+        }
+    }
+
+    fn reflects_off_coat(
+        &self,
+        ray_in: &crate::ray::Ray,
+        hit_record: &crate::hittable::HitRecord,
+    ) -> bool {
+        let cos_theta = (-ray_in.dir().unit_vector()).dot(&hit_record.normal);
+        hashed_sample(self.salt, ray_in, hit_record)
+            < crate::fresnel::dielectric(cos_theta, self.index_of_refraction)
+    }
+
+    // Weight of the base's scattering seen through the coat. The chance of
+    // entering the coat cancels with the chance of choosing the base.
+    fn through_coat(&self, cos_o: f64, cos_i: f64) -> super::vec3::Color {
+        let cos_i = f64::min(f64::abs(cos_i), 1.0);
+        let refracted_cosine = |cosine: f64| {
+            let sin_squared =
+                (1.0 - cosine * cosine) / (self.index_of_refraction * self.index_of_refraction);
+            f64::sqrt(f64::max(1.0 - sin_squared, 1e-6))
+        };
+        // Longer paths through the coat at grazing angles, normalized so the
+        // round trip at normal incidence is `tint`
+        let path_length = 0.5 * (1.0 / refracted_cosine(cos_o) + 1.0 / refracted_cosine(cos_i));
+        let exit = 1.0 - crate::fresnel::dielectric(cos_i, self.index_of_refraction);
+
+        super::vec3::Color::new(
+            exit * f64::powf(self.tint.x(), path_length),
+            exit * f64::powf(self.tint.y(), path_length),
+            exit * f64::powf(self.tint.z(), path_length),
+        )
+    }
+}
+
+impl Material for Layered {
+    fn scatter(
+        &self,
+        ray_in: &crate::ray::Ray,
+        hit_record: &crate::hittable::HitRecord,
+        scatter_record: &mut ScatterRecord,
+        sampler: &mut dyn crate::sampler::Sampler,
+    ) -> bool {
+        if !hit_record.front_face {
+            return self
+                .base
+                .scatter(ray_in, hit_record, scatter_record, sampler);
+        }
+        let unit_direction = ray_in.dir().unit_vector();
+
+        if self.reflects_off_coat(ray_in, hit_record) {
+            // The coat's reflectance is also the chance of getting here
+            scatter_record.attenuation = super::vec3::Color::new(1.0, 1.0, 1.0);
+            if self.distribution.effectively_smooth() {
+                scatter_record.pdf = None;
+                scatter_record.is_specular = true;
+                scatter_record.specular_ray = crate::ray::Ray::new(
+                    hit_record.point.clone(),
+                    super::vec3::Vec3::reflect(&unit_direction, &hit_record.normal),
+                );
+            } else {
+                scatter_record.pdf = Some(Box::new(crate::pdf::MicrofacetReflectionPdf::new(
+                    &hit_record.normal,
+                    &-unit_direction,
+                    self.distribution,
+                )));
+                scatter_record.is_specular = false;
+            }
+            return true;
+        }
+
+        if !self
+            .base
+            .scatter(ray_in, hit_record, scatter_record, sampler)
+        {
+            return false;
+        }
+        if scatter_record.is_specular {
+            let cos_o = (-&unit_direction).dot(&hit_record.normal);
+            let cos_i = scatter_record
+                .specular_ray
+                .dir()
+                .unit_vector()
+                .dot(&hit_record.normal);
+            scatter_record.attenuation =
+                scatter_record.attenuation.clone() * self.through_coat(cos_o, cos_i);
+        }
+
+        true
+    }
+
+    fn eval(
+        &self,
+        ray_in: &crate::ray::Ray,
+        hit_record: &crate::hittable::HitRecord,
+        direction: &crate::vec3::Vec3,
+    ) -> crate::vec3::Color {
+        if !hit_record.front_face {
+            return self.base.eval(ray_in, hit_record, direction);
+        }
+        let frame = crate::onb::Onb::new(&hit_record.normal);
+        let wo = frame.to_local(&-ray_in.dir().unit_vector());
+        let wi = frame.to_local(&direction.unit_vector());
+
+        if !self.reflects_off_coat(ray_in, hit_record) {
+            return self.base.eval(ray_in, hit_record, direction)
+                * self.through_coat(wo.z(), wi.z());
+        }
+        let half_vector = &wo + &wi;
+        if self.distribution.effectively_smooth()
+            || wo.z() <= 0.0
+            || wi.z() <= 0.0
+            || half_vector.near_zero()
+        {
+            return super::vec3::Color::zeroed();
+        }
+        let wm = half_vector.unit_vector();
+
+        let reflectance = crate::fresnel::dielectric(wo.dot(&wm), self.index_of_refraction)
+            / crate::fresnel::dielectric(wo.z(), self.index_of_refraction);
+        let value =
+            self.distribution.d(&wm) * self.distribution.g(&wo, &wi) * reflectance / (4.0 * wo.z());
+        super::vec3::Color::new(value, value, value)
+    }
+
+    fn emitted(
+        &self,
+        ray_in: &crate::ray::Ray,
+        hit_record: &crate::hittable::HitRecord,
+    ) -> crate::vec3::Color {
+        self.base.emitted(ray_in, hit_record)
+    }
+
+    fn emitted_spectrum(
+        &self,
+        ray_in: &crate::ray::Ray,
+        hit_record: &crate::hittable::HitRecord,
+        wavelengths: &crate::spectrum::SampledWavelengths,
+    ) -> crate::spectrum::SampledSpectrum {
+        self.base.emitted_spectrum(ray_in, hit_record, wavelengths)
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.base.is_dispersive()
+    }
+}
+
+// Tells apart the choices of nested `Mix` and `Layered` materials, which
+// would otherwise all hash the same ray and hit
+fn next_salt() -> u64 {
+    static NEXT_SALT: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+    NEXT_SALT.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+}
+
+// Uniform in [0, 1), the same every time the same ray hits the same point
+fn hashed_sample(
+    salt: u64,
+    ray_in: &crate::ray::Ray,
+    hit_record: &crate::hittable::HitRecord,
+) -> f64 {
+    let (origin, direction) = (ray_in.origin(), ray_in.dir());
+    crate::sampler::unit_float(crate::sampler::hash(&[
+        salt,
+        origin.x().to_bits(),
+        origin.y().to_bits(),
+        origin.z().to_bits(),
+        direction.x().to_bits(),
+        direction.y().to_bits(),
+        direction.z().to_bits(),
+        hit_record.t.to_bits(),
+    ]))
+}
+
+// BSDF times the cosine term of a rough dielectric interface of relative IOR
+// `eta`, for reflection and transmission. Directions are in the local frame.
+fn microfacet_dielectric(
+    distribution: &crate::microfacet::TrowbridgeReitz,
+    wo: &super::vec3::Vec3,
+    wi: &super::vec3::Vec3,
+    eta: f64,
+) -> f64 {
+    let Some(wm) = crate::microfacet::generalized_half_vector(wo, wi, eta) else {
+        return 0.0;
+    };
+
+    let reflectance = crate::fresnel::dielectric(wo.dot(&wm), eta);
+    let d_g = distribution.d(&wm) * distribution.g(wo, wi);
+    if wi.z() > 0.0 {
+        reflectance * d_g / (4.0 * wo.z())
+    } else {
+        let denominator = wi.dot(&wm) + wo.dot(&wm) / eta;
+        (1.0 - reflectance) * d_g * f64::abs(wi.dot(&wm) * wo.dot(&wm))
+            / (wo.z() * denominator * denominator)
+    }
+}
+
+fn dispersed_ior(
+    index_of_refraction: f64,
+    dispersion: Option<&crate::spectrum::Dispersion>,
+    ray: &crate::ray::Ray,
+) -> f64 {
+    match (dispersion, ray.wavelength()) {
+        (Some(dispersion), Some(wavelength)) => dispersion.index_of_refraction(wavelength),
+        _ => index_of_refraction,
+    }
+}
+
+// Absorption coefficient that leaves `tint` of the light after traveling
+// `distance` through the medium
+pub fn absorption_from_tint(tint: &super::vec3::Color, distance: f64) -> super::vec3::Color {
+    let coefficient = |transmitted: f64| -f64::ln(f64::clamp(transmitted, 1e-6, 1.0)) / distance;
+    super::vec3::Color::new(
+        coefficient(tint.x()),
+        coefficient(tint.y()),
+        coefficient(tint.z()),
+    )
+}
+
+pub struct DiffuseLight {
+    emit: super::vec3::Color,
+    // Emission spectrum and its scale, `emit` then being its color
+    spectrum: Option<(Box<dyn crate::spectrum::Spectrum>, f64)>,
+}
+
+impl DiffuseLight {
+    pub const fn new(emit: super::vec3::Color) -> Self {
+        Self {
+            emit,
+            spectrum: None,
+        }
+    }
+
+    pub fn with_spectrum(spectrum: Box<dyn crate::spectrum::Spectrum>, scale: f64) -> Self {
+        Self {
+            emit: scale * crate::spectrum::spectrum_to_rgb(spectrum.as_ref()),
+            spectrum: Some((spectrum, scale)),
+        }
+    }
+}
+
+impl Material for DiffuseLight {
+    fn scatter(
+        &self,
+        _ray_in: &crate::ray::Ray,
+        _hit_record: &crate::hittable::HitRecord,
+        _scatter_record: &mut ScatterRecord,
+        _sampler: &mut dyn crate::sampler::Sampler,
+    ) -> bool {
+        false
+    }
+
+    fn emitted(
+        &self,
+        _ray_in: &crate::ray::Ray,
+        hit_record: &crate::hittable::HitRecord,
+    ) -> crate::vec3::Color {
+        if !hit_record.front_face {
+            return crate::vec3::Color::zeroed();
+        }
+        self.emit.clone()
+    }
+
+    fn emitted_spectrum(
+        &self,
+        ray_in: &crate::ray::Ray,
+        hit_record: &crate::hittable::HitRecord,
+        wavelengths: &crate::spectrum::SampledWavelengths,
+    ) -> crate::spectrum::SampledSpectrum {
+        match &self.spectrum {
+            Some(_) if !hit_record.front_face => crate::spectrum::SampledSpectrum::constant(0.0),
+            Some((spectrum, scale)) => spectrum.sample(wavelengths) * *scale,
+            None => crate::spectrum::SampledSpectrum::from_rgb(
+                &self.emitted(ray_in, hit_record),
+                wavelengths,
+            ),
+        }
+    }
 }
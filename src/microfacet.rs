@@ -0,0 +1,133 @@
+use crate::{consts, vec3};
+
+// Trowbridge-Reitz (GGX) distribution of microfacet normals with Smith
+// masking-shadowing. Directions are in the local shading frame, where the
+// macro surface normal is +z.
+#[derive(Clone, Copy, Debug)]
+pub struct TrowbridgeReitz {
+    alpha_x: f64,
+    alpha_y: f64,
+}
+
+impl TrowbridgeReitz {
+    pub fn new(alpha_x: f64, alpha_y: f64) -> Self {
+        Self { alpha_x, alpha_y }
+    }
+
+    // Perceptually linear roughness in [0, 1] to the distribution's alpha
+    pub fn roughness_to_alpha(roughness: f64) -> f64 {
+        roughness * roughness
+    }
+
+    // Below this the surface is treated as a perfect mirror, sampling such a
+    // peaked distribution only produces fireflies
+    pub fn effectively_smooth(&self) -> bool {
+        f64::max(self.alpha_x, self.alpha_y) < 1e-3
+    }
+
+    pub fn d(&self, wm: &vec3::Vec3) -> f64 {
+        if wm.z() <= 0.0 {
+            return 0.0;
+        }
+        let x = wm.x() / self.alpha_x;
+        let y = wm.y() / self.alpha_y;
+        let denominator = x * x + y * y + wm.z() * wm.z();
+
+        1.0 / (consts::PI * self.alpha_x * self.alpha_y * denominator * denominator)
+    }
+
+    // Smith's auxiliary function, the ratio of hidden to visible microfacet area
+    pub fn lambda(&self, w: &vec3::Vec3) -> f64 {
+        let z_squared = w.z() * w.z();
+        if z_squared == 0.0 {
+            return consts::INFINITY;
+        }
+        let x = self.alpha_x * w.x();
+        let y = self.alpha_y * w.y();
+
+        (f64::sqrt(1.0 + (x * x + y * y) / z_squared) - 1.0) / 2.0
+    }
+
+    pub fn g1(&self, w: &vec3::Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    // Height correlated masking-shadowing
+    pub fn g(&self, wo: &vec3::Vec3, wi: &vec3::Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    // Density of the normals visible from `w`
+    pub fn visible_d(&self, w: &vec3::Vec3, wm: &vec3::Vec3) -> f64 {
+        if w.z() == 0.0 {
+            return 0.0;
+        }
+        self.g1(w) / f64::abs(w.z()) * self.d(wm) * f64::abs(w.dot(wm))
+    }
+
+    // Samples a normal visible from `w` (Heitz 2018), distributed as `visible_d`
+    pub fn sample_wm(&self, w: &vec3::Vec3, sample: (f64, f64)) -> vec3::Vec3 {
+        // Stretch the view direction so the distribution becomes a hemisphere
+        let mut wh =
+            vec3::Vec3::new(self.alpha_x * w.x(), self.alpha_y * w.y(), w.z()).unit_vector();
+        if wh.z() < 0.0 {
+            wh = -wh;
+        }
+        let t1 = if wh.z() < 0.99999 {
+            vec3::Vec3::new(0.0, 0.0, 1.0).cross(&wh).unit_vector()
+        } else {
+            vec3::Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = wh.cross(&t1);
+
+        // Uniform disk point warped onto the projection of the visible hemisphere
+        let radius = f64::sqrt(sample.0);
+        let phi = 2.0 * consts::PI * sample.1;
+        let px = radius * f64::cos(phi);
+        let mut py = radius * f64::sin(phi);
+        let h = f64::sqrt(1.0 - px * px);
+        let s = (1.0 + wh.z()) / 2.0;
+        py = (1.0 - s) * h + s * py;
+        let pz = f64::sqrt(f64::max(0.0, 1.0 - px * px - py * py));
+
+        let nh = px * t1 + py * t2 + pz * wh;
+        vec3::Vec3::new(
+            self.alpha_x * nh.x(),
+            self.alpha_y * nh.y(),
+            f64::max(1e-6, nh.z()),
+        )
+        .unit_vector()
+    }
+}
+
+// Microfacet normal that turns `wo` into `wi`, by reflection when both are
+// above the surface and by refraction into a medium of relative IOR `eta`
+// otherwise. Returned facing up, None when the pair is degenerate or either
+// direction would see the back of the microfacet.
+pub fn generalized_half_vector(wo: &vec3::Vec3, wi: &vec3::Vec3, eta: f64) -> Option<vec3::Vec3> {
+    let reflect = wo.z() * wi.z() > 0.0;
+    let half_vector = if reflect { wo + wi } else { wo + &(eta * wi) };
+    if wo.z() == 0.0 || wi.z() == 0.0 || half_vector.near_zero() {
+        return None;
+    }
+    let mut wm = half_vector.unit_vector();
+    if wm.z() < 0.0 {
+        wm = -wm;
+    }
+    if wm.dot(wi) * wi.z() < 0.0 || wm.dot(wo) * wo.z() < 0.0 {
+        return None;
+    }
+
+    Some(wm)
+}
+
+// Direction `wo` refracts to through a microfacet with normal `wm` into a
+// medium of relative IOR `eta`, None on total internal reflection
+pub fn refract(wo: &vec3::Vec3, wm: &vec3::Vec3, eta: f64) -> Option<vec3::Vec3> {
+    let cos_theta = wo.dot(wm);
+    if (1.0 - cos_theta * cos_theta) / (eta * eta) >= 1.0 {
+        return None;
+    }
+
+    Some(vec3::Vec3::refract(&-wo, wm, 1.0 / eta))
+}
@@ -0,0 +1,64 @@
+use crate::vec3;
+
+// Orthonormal basis built around a single direction, used to move sampled
+// directions from a local frame (where w is "up") into world space.
+pub struct Onb {
+    axis: [vec3::Vec3; 3],
+}
+
+impl Onb {
+    pub fn new(w: &vec3::Vec3) -> Self {
+        let unit_w = w.unit_vector();
+        let a = if f64::abs(unit_w.x()) > 0.9 {
+            vec3::Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            vec3::Vec3::new(1.0, 0.0, 0.0)
+        };
+        let v = unit_w.cross(&a).unit_vector();
+        let u = unit_w.cross(&v);
+
+        Self {
+            axis: [u, v, unit_w],
+        }
+    }
+
+    // Basis with w along `normal` and u along `tangent` projected into the
+    // plane normal to it, so it varies smoothly wherever the tangent does.
+    // Falls back to `new` where the tangent is parallel to the normal.
+    pub fn from_normal_and_tangent(normal: &vec3::Vec3, tangent: &vec3::Vec3) -> Self {
+        let unit_w = normal.unit_vector();
+        let projected = tangent - unit_w.dot(tangent) * &unit_w;
+        if projected.length_squared() < 1e-12 {
+            return Self::new(normal);
+        }
+        let u = projected.unit_vector();
+        let v = unit_w.cross(&u);
+
+        Self {
+            axis: [u, v, unit_w],
+        }
+    }
+
+    pub fn u(&self) -> &vec3::Vec3 {
+        &self.axis[0]
+    }
+    pub fn v(&self) -> &vec3::Vec3 {
+        &self.axis[1]
+    }
+    pub fn w(&self) -> &vec3::Vec3 {
+        &self.axis[2]
+    }
+
+    pub fn local(&self, a: f64, b: f64, c: f64) -> vec3::Vec3 {
+        a * self.u() + b * self.v() + c * self.w()
+    }
+
+    pub fn local_vec(&self, a: &vec3::Vec3) -> vec3::Vec3 {
+        self.local(a.x(), a.y(), a.z())
+    }
+
+    // Inverse of `local_vec`: expresses a world space vector in this basis.
+    pub fn to_local(&self, a: &vec3::Vec3) -> vec3::Vec3 {
+        vec3::Vec3::new(a.dot(self.u()), a.dot(self.v()), a.dot(self.w()))
+    }
+}
@@ -0,0 +1,326 @@
+use crate::{consts, fresnel, hittable, microfacet, onb, sampler, vec3};
+
+pub trait Pdf {
+    fn value(&self, direction: &vec3::Vec3) -> f64;
+    fn generate(&self, sampler: &mut dyn sampler::Sampler) -> vec3::Vec3;
+}
+
+pub struct SpherePdf;
+
+impl Pdf for SpherePdf {
+    fn value(&self, _direction: &vec3::Vec3) -> f64 {
+        1.0 / (4.0 * consts::PI)
+    }
+
+    fn generate(&self, sampler: &mut dyn sampler::Sampler) -> vec3::Vec3 {
+        vec3::Vec3::sample_unit_vector(sampler.get_2d())
+    }
+}
+
+pub struct CosinePdf {
+    uvw: onb::Onb,
+}
+
+impl CosinePdf {
+    pub fn new(w: &vec3::Vec3) -> Self {
+        Self {
+            uvw: onb::Onb::new(w),
+        }
+    }
+}
+
+impl Pdf for CosinePdf {
+    fn value(&self, direction: &vec3::Vec3) -> f64 {
+        let cosine_theta = direction.unit_vector().dot(self.uvw.w());
+        f64::max(0.0, cosine_theta / consts::PI)
+    }
+
+    fn generate(&self, sampler: &mut dyn sampler::Sampler) -> vec3::Vec3 {
+        self.uvw
+            .local_vec(&vec3::Vec3::sample_cosine_direction(sampler.get_2d()))
+    }
+}
+
+// Samples directions from `origin` towards the given objects, e.g. the lights
+pub struct HittablePdf<'a> {
+    objects: &'a dyn hittable::Hittable,
+    origin: vec3::Point3,
+}
+
+impl<'a> HittablePdf<'a> {
+    pub fn new(objects: &'a dyn hittable::Hittable, origin: vec3::Point3) -> Self {
+        Self { objects, origin }
+    }
+}
+
+impl Pdf for HittablePdf<'_> {
+    fn value(&self, direction: &vec3::Vec3) -> f64 {
+        self.objects.pdf_value(&self.origin, direction)
+    }
+
+    fn generate(&self, sampler: &mut dyn sampler::Sampler) -> vec3::Vec3 {
+        self.objects.random(&self.origin, sampler)
+    }
+}
+
+// Picks one of the two pdfs with probability `weight` for the first one
+pub struct MixturePdf<'a> {
+    pdfs: [&'a dyn Pdf; 2],
+    weight: f64,
+}
+
+impl<'a> MixturePdf<'a> {
+    pub fn new(first: &'a dyn Pdf, second: &'a dyn Pdf) -> Self {
+        Self::with_weight(first, second, 0.5)
+    }
+
+    pub fn with_weight(first: &'a dyn Pdf, second: &'a dyn Pdf, weight: f64) -> Self {
+        debug_assert!(
+            (0.0..=1.0).contains(&weight),
+            "Mixture weight must be between 0 and 1"
+        );
+        Self {
+            pdfs: [first, second],
+            weight,
+        }
+    }
+}
+
+impl Pdf for MixturePdf<'_> {
+    fn value(&self, direction: &vec3::Vec3) -> f64 {
+        self.weight * self.pdfs[0].value(direction)
+            + (1.0 - self.weight) * self.pdfs[1].value(direction)
+    }
+
+    fn generate(&self, sampler: &mut dyn sampler::Sampler) -> vec3::Vec3 {
+        if sampler.get_1d() < self.weight {
+            self.pdfs[0].generate(sampler)
+        } else {
+            self.pdfs[1].generate(sampler)
+        }
+    }
+}
+
+// Picks one of any number of pdfs in proportion to its weight, e.g. the
+// lobes of a layered material
+pub struct WeightedPdf {
+    pdfs: Vec<(f64, Box<dyn Pdf>)>,
+    total_weight: f64,
+}
+
+impl WeightedPdf {
+    pub fn new() -> Self {
+        Self {
+            pdfs: Vec::new(),
+            total_weight: 0.0,
+        }
+    }
+
+    // Pdfs without any weight are left out
+    pub fn add(&mut self, weight: f64, pdf: Box<dyn Pdf>) {
+        if weight <= 0.0 {
+            return;
+        }
+        self.pdfs.push((weight, pdf));
+        self.total_weight += weight;
+    }
+}
+
+impl Default for WeightedPdf {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Pdf for WeightedPdf {
+    fn value(&self, direction: &vec3::Vec3) -> f64 {
+        if self.total_weight <= 0.0 {
+            return 0.0;
+        }
+        self.pdfs
+            .iter()
+            .map(|(weight, pdf)| weight * pdf.value(direction))
+            .sum::<f64>()
+            / self.total_weight
+    }
+
+    fn generate(&self, sampler: &mut dyn sampler::Sampler) -> vec3::Vec3 {
+        let mut choice = sampler.get_1d() * self.total_weight;
+        for (weight, pdf) in &self.pdfs {
+            if choice < *weight {
+                return pdf.generate(sampler);
+            }
+            choice -= weight;
+        }
+        // Rounding left the choice past the end
+        match self.pdfs.last() {
+            Some((_, pdf)) => pdf.generate(sampler),
+            None => vec3::Vec3::zeroed(),
+        }
+    }
+}
+
+// Distribution of `reflected + fuzziness * random_unit_vector`, the
+// perturbed mirror direction used by fuzzy metals.
+pub struct FuzzyReflectionPdf {
+    reflected: vec3::Vec3,
+    fuzziness: f64,
+}
+
+impl FuzzyReflectionPdf {
+    pub fn new(reflected: &vec3::Vec3, fuzziness: f64) -> Self {
+        Self {
+            reflected: reflected.unit_vector(),
+            fuzziness,
+        }
+    }
+}
+
+impl Pdf for FuzzyReflectionPdf {
+    fn value(&self, direction: &vec3::Vec3) -> f64 {
+        // The generated points lie uniformly on a sphere of radius `fuzziness`
+        // around the mirror direction; convert every intersection of the
+        // direction with that sphere from area to solid angle measure.
+        let direction = direction.unit_vector();
+        let b = direction.dot(&self.reflected);
+        let discriminant = b * b - (1.0 - self.fuzziness * self.fuzziness);
+        if discriminant <= 0.0 {
+            return 0.0;
+        }
+        let disc_sqrt = f64::sqrt(discriminant);
+
+        let area_to_solid_angle: f64 = [b - disc_sqrt, b + disc_sqrt]
+            .iter()
+            .filter(|t| **t > 0.0)
+            .map(|t| t * t)
+            .sum();
+
+        area_to_solid_angle / (4.0 * consts::PI * self.fuzziness * disc_sqrt)
+    }
+
+    fn generate(&self, sampler: &mut dyn sampler::Sampler) -> vec3::Vec3 {
+        &self.reflected + &(self.fuzziness * vec3::Vec3::sample_unit_vector(sampler.get_2d()))
+    }
+}
+
+// Microfacet normals sampled from the ones visible from `wo`, reflected
+// about the sampled normal
+pub struct MicrofacetReflectionPdf {
+    frame: onb::Onb,
+    // Outgoing direction in the local frame of the surface
+    wo: vec3::Vec3,
+    distribution: microfacet::TrowbridgeReitz,
+}
+
+impl MicrofacetReflectionPdf {
+    pub fn new(
+        normal: &vec3::Vec3,
+        wo: &vec3::Vec3,
+        distribution: microfacet::TrowbridgeReitz,
+    ) -> Self {
+        Self::with_frame(onb::Onb::new(normal), wo, distribution)
+    }
+
+    // Anisotropic distributions need the frame the material evaluates them in
+    pub fn with_frame(
+        frame: onb::Onb,
+        wo: &vec3::Vec3,
+        distribution: microfacet::TrowbridgeReitz,
+    ) -> Self {
+        let wo = frame.to_local(&wo.unit_vector());
+        Self {
+            frame,
+            wo,
+            distribution,
+        }
+    }
+}
+
+impl Pdf for MicrofacetReflectionPdf {
+    fn value(&self, direction: &vec3::Vec3) -> f64 {
+        let wi = self.frame.to_local(&direction.unit_vector());
+        let half_vector = &self.wo + &wi;
+        if wi.z() <= 0.0 || half_vector.near_zero() {
+            return 0.0;
+        }
+        let wm = half_vector.unit_vector();
+
+        // Jacobian of the reflection from normals to directions
+        self.distribution.visible_d(&self.wo, &wm) / (4.0 * f64::abs(self.wo.dot(&wm)))
+    }
+
+    fn generate(&self, sampler: &mut dyn sampler::Sampler) -> vec3::Vec3 {
+        let wm = self.distribution.sample_wm(&self.wo, sampler.get_2d());
+        let wi = vec3::Vec3::reflect(&-&self.wo, &wm);
+
+        self.frame.local_vec(&wi)
+    }
+}
+
+// Visible normal sampling for rough dielectrics, reflecting or refracting
+// about the sampled normal in proportion to the Fresnel reflectance. Samples
+// ending up on the wrong side of the surface come back as the zero vector,
+// which has zero density.
+pub struct MicrofacetDielectricPdf {
+    frame: onb::Onb,
+    // Outgoing direction in the local frame of the surface, always above it
+    wo: vec3::Vec3,
+    // IOR below the surface over the IOR above it
+    eta: f64,
+    distribution: microfacet::TrowbridgeReitz,
+}
+
+impl MicrofacetDielectricPdf {
+    pub fn new(
+        normal: &vec3::Vec3,
+        wo: &vec3::Vec3,
+        eta: f64,
+        distribution: microfacet::TrowbridgeReitz,
+    ) -> Self {
+        let frame = onb::Onb::new(normal);
+        let wo = frame.to_local(&wo.unit_vector());
+        Self {
+            frame,
+            wo,
+            eta,
+            distribution,
+        }
+    }
+}
+
+impl Pdf for MicrofacetDielectricPdf {
+    fn value(&self, direction: &vec3::Vec3) -> f64 {
+        if direction.near_zero() {
+            return 0.0;
+        }
+        let wi = self.frame.to_local(&direction.unit_vector());
+        let Some(wm) = microfacet::generalized_half_vector(&self.wo, &wi, self.eta) else {
+            return 0.0;
+        };
+        let reflectance = fresnel::dielectric(self.wo.dot(&wm), self.eta);
+        let visible_d = self.distribution.visible_d(&self.wo, &wm);
+
+        // Jacobians of the reflection and refraction from normals to directions
+        if wi.z() > 0.0 {
+            reflectance * visible_d / (4.0 * f64::abs(self.wo.dot(&wm)))
+        } else {
+            let denominator = wi.dot(&wm) + self.wo.dot(&wm) / self.eta;
+            (1.0 - reflectance) * visible_d * f64::abs(wi.dot(&wm)) / (denominator * denominator)
+        }
+    }
+
+    fn generate(&self, sampler: &mut dyn sampler::Sampler) -> vec3::Vec3 {
+        let wm = self.distribution.sample_wm(&self.wo, sampler.get_2d());
+        let reflectance = fresnel::dielectric(self.wo.dot(&wm), self.eta);
+        let choice = sampler.get_1d();
+
+        let (wi, reflected) = match microfacet::refract(&self.wo, &wm, self.eta) {
+            Some(refracted) if choice >= reflectance => (refracted, false),
+            _ => (vec3::Vec3::reflect(&-&self.wo, &wm), true),
+        };
+        if (wi.z() > 0.0) != reflected {
+            return vec3::Vec3::zeroed();
+        }
+        self.frame.local_vec(&wi)
+    }
+}
@@ -0,0 +1,82 @@
+// How far a render has got, a work unit being one tile of one pass
+pub struct Progress {
+    pub completed_units: u64,
+    pub total_units: u64,
+    pub elapsed: std::time::Duration,
+    pub camera_rays: u64,
+}
+
+impl Progress {
+    pub fn fraction(&self) -> f64 {
+        if self.total_units == 0 {
+            return 1.0;
+        }
+        self.completed_units as f64 / self.total_units as f64
+    }
+
+    // Extrapolated from the time taken so far, None until a unit is done
+    pub fn eta(&self) -> Option<std::time::Duration> {
+        if self.completed_units == 0 {
+            return None;
+        }
+        let remaining = self.total_units.saturating_sub(self.completed_units);
+        Some(
+            self.elapsed
+                .mul_f64(remaining as f64 / self.completed_units as f64),
+        )
+    }
+
+    pub fn rays_per_second(&self) -> f64 {
+        rate(self.camera_rays, self.elapsed)
+    }
+}
+
+// Summary handed over once a render is done
+pub struct RenderStats {
+    pub elapsed: std::time::Duration,
+    pub camera_rays: u64,
+    // The time budget ran out or the render was cancelled
+    pub stopped_early: bool,
+    // Only collected when asked for, see `Camera::collect_statistics`
+    pub statistics: Option<crate::stats::Statistics>,
+}
+
+impl RenderStats {
+    pub fn rays_per_second(&self) -> f64 {
+        rate(self.camera_rays, self.elapsed)
+    }
+}
+
+// Receives what a render is doing, every method defaults to doing nothing
+pub trait ProgressObserver {
+    fn on_start(&mut self, _width: usize, _height: usize) {}
+    fn on_progress(&mut self, _progress: &Progress) {}
+    fn on_finish(&mut self, _stats: &RenderStats) {}
+    fn on_message(&mut self, _message: &str) {}
+    fn on_warning(&mut self, _message: &str) {}
+}
+
+pub struct SilentProgress;
+
+impl ProgressObserver for SilentProgress {}
+
+// Prints messages and warnings to stderr, ignoring progress
+pub struct ConsoleProgress;
+
+impl ProgressObserver for ConsoleProgress {
+    fn on_message(&mut self, message: &str) {
+        eprintln!("{message}");
+    }
+
+    fn on_warning(&mut self, message: &str) {
+        eprintln!("{message}");
+    }
+}
+
+fn rate(count: u64, elapsed: std::time::Duration) -> f64 {
+    let seconds = elapsed.as_secs_f64();
+    if seconds <= 0.0 {
+        return 0.0;
+    }
+    count as f64 / seconds
+}
@@ -0,0 +1,383 @@
+use rand::{Rng, SeedableRng};
+
+// Source of the random numbers used to trace a single camera sample. Every
+// sample starts with `start_pixel_sample`, after which each call to `get_1d`
+// or `get_2d` consumes the next dimension of the sample vector.
+pub trait Sampler {
+    fn start_pixel_sample(&mut self, row: usize, column: usize, sample_index: u32);
+    fn get_1d(&mut self) -> f64;
+    fn get_2d(&mut self) -> (f64, f64);
+
+    // Position inside the pixel, always the first dimensions of a sample
+    fn get_pixel_2d(&mut self) -> (f64, f64) {
+        self.get_2d()
+    }
+
+    fn seed(&self) -> u64;
+    // Samples only depend on the seed and the pixel sample, so restoring the
+    // seed is enough to continue a render where it stopped
+    fn set_seed(&mut self, seed: u64);
+}
+
+// Uncorrelated uniform random numbers
+pub struct IndependentSampler {
+    seed: u64,
+    rng_gen: rand::rngs::SmallRng,
+}
+
+impl IndependentSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            rng_gen: rand::rngs::SmallRng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn start_pixel_sample(&mut self, row: usize, column: usize, sample_index: u32) {
+        self.rng_gen = rand::rngs::SmallRng::seed_from_u64(hash(&[
+            self.seed,
+            row as u64,
+            column as u64,
+            sample_index as u64,
+        ]));
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        self.rng_gen.gen()
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.rng_gen.gen(), self.rng_gen.gen())
+    }
+
+    fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+}
+
+// Jittered samples, one per stratum of an `x_strata` by `y_strata` grid. Each
+// dimension visits the strata in its own random order so dimensions don't
+// correlate with each other.
+pub struct StratifiedSampler {
+    x_strata: u32,
+    y_strata: u32,
+    seed: u64,
+    pixel_seed: u64,
+    sample_index: u32,
+    dimension: u64,
+}
+
+impl StratifiedSampler {
+    pub fn new(x_strata: u32, y_strata: u32, seed: u64) -> Self {
+        debug_assert!(x_strata >= 1 && y_strata >= 1, "Need at least one stratum");
+        Self {
+            x_strata,
+            y_strata,
+            seed,
+            pixel_seed: seed,
+            sample_index: 0,
+            dimension: 0,
+        }
+    }
+
+    fn next_dimension_hash(&mut self) -> u64 {
+        self.dimension += 1;
+        hash(&[self.pixel_seed, self.dimension])
+    }
+
+    // Samples past the number of strata start a new randomly ordered round
+    fn stratum_and_jitter(&self, strata_count: u32, dimension_hash: u64) -> (u32, f64) {
+        let round = self.sample_index / strata_count;
+        let stratum = permutation_element(
+            self.sample_index % strata_count,
+            strata_count,
+            hash(&[dimension_hash, round as u64]) as u32,
+        );
+        let jitter = unit_float(hash(&[dimension_hash, self.sample_index as u64]));
+
+        (stratum, jitter)
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_pixel_sample(&mut self, row: usize, column: usize, sample_index: u32) {
+        self.pixel_seed = hash(&[self.seed, row as u64, column as u64]);
+        self.sample_index = sample_index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let dimension_hash = self.next_dimension_hash();
+        let strata_count = self.x_strata * self.y_strata;
+        let (stratum, jitter) = self.stratum_and_jitter(strata_count, dimension_hash);
+
+        (stratum as f64 + jitter) / strata_count as f64
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let dimension_hash = self.next_dimension_hash();
+        let strata_count = self.x_strata * self.y_strata;
+        let (stratum, jitter_x) = self.stratum_and_jitter(strata_count, dimension_hash);
+        let jitter_y = unit_float(hash(&[dimension_hash, self.sample_index as u64, 1]));
+
+        (
+            ((stratum % self.x_strata) as f64 + jitter_x) / self.x_strata as f64,
+            ((stratum / self.x_strata) as f64 + jitter_y) / self.y_strata as f64,
+        )
+    }
+
+    fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+}
+
+const HALTON_PRIMES: [u32; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131,
+];
+
+// Owen scrambled Halton sequence, scrambled separately for each pixel and
+// dimension. Dimensions past the prime table fall back to independent numbers.
+pub struct HaltonSampler {
+    seed: u64,
+    pixel_seed: u64,
+    sample_index: u32,
+    dimension: usize,
+}
+
+impl HaltonSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            pixel_seed: seed,
+            sample_index: 0,
+            dimension: 0,
+        }
+    }
+
+    fn sample_dimension(&self, dimension: usize) -> f64 {
+        let dimension_hash = hash(&[self.pixel_seed, dimension as u64]);
+        let Some(base) = HALTON_PRIMES.get(dimension) else {
+            return unit_float(hash(&[
+                self.pixel_seed,
+                dimension as u64,
+                self.sample_index as u64,
+            ]));
+        };
+
+        owen_scrambled_radical_inverse(*base, self.sample_index as u64, dimension_hash)
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_pixel_sample(&mut self, row: usize, column: usize, sample_index: u32) {
+        self.pixel_seed = hash(&[self.seed, row as u64, column as u64]);
+        self.sample_index = sample_index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        self.dimension += 1;
+        self.sample_dimension(self.dimension - 1)
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        self.dimension += 2;
+        (
+            self.sample_dimension(self.dimension - 2),
+            self.sample_dimension(self.dimension - 1),
+        )
+    }
+
+    fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+}
+
+// Owen scrambled Sobol points. Only the first two Sobol dimensions are used,
+// every 1D/2D request gets its own scrambling and shuffled sample order
+// ("padding"), which keeps the good 2D stratification for all of them.
+pub struct SobolSampler {
+    seed: u64,
+    pixel_seed: u64,
+    sample_index: u32,
+    dimension: u64,
+}
+
+impl SobolSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            pixel_seed: seed,
+            sample_index: 0,
+            dimension: 0,
+        }
+    }
+
+    fn next_dimension_hash(&mut self) -> u64 {
+        self.dimension += 1;
+        hash(&[self.pixel_seed, self.dimension])
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_pixel_sample(&mut self, row: usize, column: usize, sample_index: u32) {
+        self.pixel_seed = hash(&[self.seed, row as u64, column as u64]);
+        self.sample_index = sample_index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let dimension_hash = self.next_dimension_hash();
+        let index = nested_uniform_scramble(self.sample_index, dimension_hash as u32);
+
+        sobol_float(nested_uniform_scramble(
+            sobol(index, 0),
+            (dimension_hash >> 32) as u32,
+        ))
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let dimension_hash = self.next_dimension_hash();
+        let index = nested_uniform_scramble(self.sample_index, dimension_hash as u32);
+        let second_hash = hash(&[dimension_hash]);
+
+        (
+            sobol_float(nested_uniform_scramble(
+                sobol(index, 0),
+                (dimension_hash >> 32) as u32,
+            )),
+            sobol_float(nested_uniform_scramble(sobol(index, 1), second_hash as u32)),
+        )
+    }
+
+    fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+}
+
+pub(crate) fn hash(values: &[u64]) -> u64 {
+    // splitmix64 finalizer folded over the values
+    values.iter().fold(0x9e3779b97f4a7c15, |state, value| {
+        let mut z = (state ^ value).wrapping_add(0x9e3779b97f4a7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    })
+}
+
+pub(crate) fn unit_float(value: u64) -> f64 {
+    (value >> 11) as f64 / (1u64 << 53) as f64
+}
+
+fn sobol_float(value: u32) -> f64 {
+    value as f64 / (1u64 << 32) as f64
+}
+
+// Radical inverse with every digit, including the leading zeros, permuted
+// depending on the digits before it
+fn owen_scrambled_radical_inverse(base: u32, mut index: u64, seed: u64) -> f64 {
+    let inverse_base = 1.0 / base as f64;
+    let mut reversed_digits = 0u64;
+    let mut inverse_base_power = 1.0;
+    let mut digit_index = 0u64;
+    while (base - 1) as f64 * inverse_base_power >= f64::EPSILON {
+        let next = index / base as u64;
+        let digit = (index - next * base as u64) as u32;
+        let digit_hash = hash(&[seed, digit_index, reversed_digits]) as u32;
+        let digit = permutation_element(digit, base, digit_hash);
+        reversed_digits = reversed_digits * base as u64 + digit as u64;
+        inverse_base_power *= inverse_base;
+        digit_index += 1;
+        index = next;
+    }
+
+    f64::min(
+        reversed_digits as f64 * inverse_base_power,
+        1.0 - f64::EPSILON,
+    )
+}
+
+// Element `index` of a pseudo random permutation of 0..length (Kensler 2013)
+fn permutation_element(mut index: u32, length: u32, seed: u32) -> u32 {
+    let mut mask = length.wrapping_sub(1);
+    mask |= mask >> 1;
+    mask |= mask >> 2;
+    mask |= mask >> 4;
+    mask |= mask >> 8;
+    mask |= mask >> 16;
+    loop {
+        index ^= seed;
+        index = index.wrapping_mul(0xe170893d);
+        index ^= seed >> 16;
+        index ^= (index & mask) >> 4;
+        index ^= seed >> 8;
+        index = index.wrapping_mul(0x0929eb3f);
+        index ^= seed >> 23;
+        index ^= (index & mask) >> 1;
+        index = index.wrapping_mul(1 | seed >> 27);
+        index = index.wrapping_mul(0x6935fa69);
+        index ^= (index & mask) >> 11;
+        index = index.wrapping_mul(0x74dcb303);
+        index ^= (index & mask) >> 2;
+        index = index.wrapping_mul(0x9e501cc3);
+        index ^= (index & mask) >> 2;
+        index = index.wrapping_mul(0xc860a3df);
+        index &= mask;
+        index ^= index >> 5;
+        if index < length {
+            break;
+        }
+    }
+
+    (index + seed % length) % length
+}
+
+// First two dimensions of the Sobol sequence, as 0.32 fixed point
+fn sobol(index: u32, dimension: u32) -> u32 {
+    if dimension == 0 {
+        return index.reverse_bits();
+    }
+    let mut result = 0u32;
+    let mut direction = 1u32 << 31;
+    let mut remaining = index;
+    while remaining != 0 {
+        if remaining & 1 == 1 {
+            result ^= direction;
+        }
+        remaining >>= 1;
+        direction ^= direction >> 1;
+    }
+
+    result
+}
+
+// Hash based Owen scrambling (Burley 2020)
+fn nested_uniform_scramble(value: u32, seed: u32) -> u32 {
+    let mut x = value.reverse_bits();
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+
+    x.reverse_bits()
+}
@@ -0,0 +1,536 @@
+use crate::vec3;
+
+// Fraunhofer d line, where catalogs quote a glass's index of refraction
+pub const REFERENCE_WAVELENGTH: f64 = 587.6;
+
+// Wavelength dependent index of refraction, coefficients taking wavelengths
+// in micrometers as is customary
+#[derive(Clone, Copy, Debug)]
+pub enum Dispersion {
+    // n = a + b / λ²
+    Cauchy { a: f64, b: f64 },
+    // n² = 1 + Σ b λ² / (λ² - c)
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+impl Dispersion {
+    pub const BK7: Self = Self::Sellmeier {
+        b: [1.03961212, 0.231792344, 1.01046945],
+        c: [0.00600069867, 0.0200179144, 103.560653],
+    };
+    pub const FUSED_SILICA: Self = Self::Sellmeier {
+        b: [0.6961663, 0.4079426, 0.8974794],
+        c: [0.0046791483, 0.0135120631, 97.9340025],
+    };
+    pub const DIAMOND: Self = Self::Sellmeier {
+        b: [4.3356, 0.3306, 0.0],
+        c: [0.011236, 0.030625, 0.0],
+    };
+
+    pub fn index_of_refraction(&self, wavelength: f64) -> f64 {
+        let micrometers = wavelength / 1000.0;
+        let squared = micrometers * micrometers;
+        match self {
+            Self::Cauchy { a, b } => a + b / squared,
+            Self::Sellmeier { b, c } => f64::sqrt(
+                1.0 + b
+                    .iter()
+                    .zip(c)
+                    .map(|(b, c)| b * squared / (squared - c))
+                    .sum::<f64>(),
+            ),
+        }
+    }
+}
+
+// Wavelengths standing for each of the RGB channels, in nanometers
+pub const CHANNEL_BANDS: [(f64, f64); 3] = [(600.0, 700.0), (500.0, 600.0), (400.0, 500.0)];
+
+// Picks one of the RGB channels and a wavelength inside its band from a
+// single sample. The returned weight keeps only that channel, scaled so the
+// estimate stays unbiased.
+pub fn sample_channel_wavelength(sample: f64) -> (f64, vec3::Color) {
+    let scaled = f64::clamp(sample, 0.0, 1.0 - f64::EPSILON) * 3.0;
+    let channel = scaled as usize;
+    let (start, end) = CHANNEL_BANDS[channel];
+
+    let mut weight = vec3::Color::zeroed();
+    weight.points[channel] = 3.0;
+    (start + (scaled - channel as f64) * (end - start), weight)
+}
+
+// Range of wavelengths traced by the spectral pipeline, in nanometers
+pub const LAMBDA_MIN: f64 = 360.0;
+pub const LAMBDA_MAX: f64 = 830.0;
+// Wavelengths carried by every spectral path
+pub const SPECTRUM_SAMPLES: usize = 4;
+
+// Spectral power or reflectance as a function of wavelength in nanometers
+pub trait Spectrum {
+    fn value(&self, wavelength: f64) -> f64;
+
+    fn sample(&self, wavelengths: &SampledWavelengths) -> SampledSpectrum {
+        let mut values = [0.0; SPECTRUM_SAMPLES];
+        for (value, wavelength) in values.iter_mut().zip(wavelengths.wavelengths) {
+            *value = self.value(wavelength);
+        }
+        SampledSpectrum::new(values)
+    }
+}
+
+pub struct ConstantSpectrum {
+    value: f64,
+}
+
+impl ConstantSpectrum {
+    pub fn new(value: f64) -> Self {
+        Self { value }
+    }
+}
+
+impl Spectrum for ConstantSpectrum {
+    fn value(&self, _wavelength: f64) -> f64 {
+        self.value
+    }
+}
+
+// Emission of an ideal black body at `temperature` kelvin, scaled so its peak is 1
+pub struct BlackbodySpectrum {
+    temperature: f64,
+    normalization: f64,
+}
+
+impl BlackbodySpectrum {
+    pub fn new(temperature: f64) -> Self {
+        assert!(
+            temperature.is_finite() && temperature > 0.0,
+            "Black body temperature must be positive, got {temperature}"
+        );
+        // Wien's displacement law
+        let peak_wavelength = 2.8977721e-3 / temperature * 1e9;
+        Self {
+            temperature,
+            normalization: 1.0 / planck(peak_wavelength, temperature),
+        }
+    }
+}
+
+impl Spectrum for BlackbodySpectrum {
+    fn value(&self, wavelength: f64) -> f64 {
+        planck(wavelength, self.temperature) * self.normalization
+    }
+}
+
+// Spectral radiance of a black body in W / (sr m³)
+fn planck(wavelength: f64, temperature: f64) -> f64 {
+    const SPEED_OF_LIGHT: f64 = 299792458.0;
+    const PLANCK: f64 = 6.62606957e-34;
+    const BOLTZMANN: f64 = 1.3806488e-23;
+
+    let meters = wavelength * 1e-9;
+    2.0 * PLANCK * SPEED_OF_LIGHT * SPEED_OF_LIGHT
+        / (f64::powi(meters, 5)
+            * (f64::exp(PLANCK * SPEED_OF_LIGHT / (meters * BOLTZMANN * temperature)) - 1.0))
+}
+
+// Piecewise linear spectrum through measured samples, zero outside of them
+pub struct TabulatedSpectrum {
+    wavelengths: Vec<f64>,
+    values: Vec<f64>,
+}
+
+impl TabulatedSpectrum {
+    // `wavelengths` must be increasing and as many as `values`
+    pub fn new(wavelengths: Vec<f64>, values: Vec<f64>) -> Self {
+        assert_eq!(
+            wavelengths.len(),
+            values.len(),
+            "Every wavelength needs a value"
+        );
+        debug_assert!(
+            wavelengths.windows(2).all(|pair| pair[0] < pair[1]),
+            "Wavelengths must be increasing"
+        );
+        Self {
+            wavelengths,
+            values,
+        }
+    }
+}
+
+impl Spectrum for TabulatedSpectrum {
+    fn value(&self, wavelength: f64) -> f64 {
+        let (Some(first), Some(last)) = (self.wavelengths.first(), self.wavelengths.last()) else {
+            return 0.0;
+        };
+        if wavelength < *first || wavelength > *last {
+            return 0.0;
+        }
+
+        let next = self
+            .wavelengths
+            .partition_point(|sample| *sample < wavelength)
+            .max(1)
+            .min(self.wavelengths.len() - 1);
+        if self.wavelengths.len() == 1 {
+            return self.values[0];
+        }
+        let t = (wavelength - self.wavelengths[next - 1])
+            / (self.wavelengths[next] - self.wavelengths[next - 1]);
+        (1.0 - t) * self.values[next - 1] + t * self.values[next]
+    }
+}
+
+// RGB color upsampled to a smooth spectrum (Smits 1999)
+pub struct RgbSpectrum {
+    rgb: vec3::Color,
+}
+
+impl RgbSpectrum {
+    pub fn new(rgb: vec3::Color) -> Self {
+        Self { rgb }
+    }
+}
+
+impl Spectrum for RgbSpectrum {
+    fn value(&self, wavelength: f64) -> f64 {
+        rgb_to_spectrum(&self.rgb, wavelength)
+    }
+}
+
+// Bins of 34nm from 380nm to 720nm
+const SMITS_WHITE: [f64; 10] = [
+    1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000,
+];
+const SMITS_CYAN: [f64; 10] = [
+    0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000,
+];
+const SMITS_MAGENTA: [f64; 10] = [
+    1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959,
+];
+const SMITS_YELLOW: [f64; 10] = [
+    0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840,
+];
+const SMITS_RED: [f64; 10] = [
+    0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149,
+];
+const SMITS_GREEN: [f64; 10] = [
+    0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025,
+];
+const SMITS_BLUE: [f64; 10] = [
+    1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496,
+];
+
+// Value at `wavelength` of the Smits spectrum of `rgb`, white plus the
+// secondary and primary colors covering the remaining components
+pub fn rgb_to_spectrum(rgb: &vec3::Color, wavelength: f64) -> f64 {
+    let basis = |table: &[f64; 10]| {
+        let position = f64::clamp((wavelength - 380.0) / 34.0 - 0.5, 0.0, 9.0);
+        let bin = usize::min(position as usize, 8);
+        let t = position - bin as f64;
+        (1.0 - t) * table[bin] + t * table[bin + 1]
+    };
+    let (r, g, b) = (
+        f64::max(rgb.x(), 0.0),
+        f64::max(rgb.y(), 0.0),
+        f64::max(rgb.z(), 0.0),
+    );
+
+    if r <= g && r <= b {
+        r * basis(&SMITS_WHITE)
+            + if g <= b {
+                (g - r) * basis(&SMITS_CYAN) + (b - g) * basis(&SMITS_BLUE)
+            } else {
+                (b - r) * basis(&SMITS_CYAN) + (g - b) * basis(&SMITS_GREEN)
+            }
+    } else if g <= r && g <= b {
+        g * basis(&SMITS_WHITE)
+            + if r <= b {
+                (r - g) * basis(&SMITS_MAGENTA) + (b - r) * basis(&SMITS_BLUE)
+            } else {
+                (b - g) * basis(&SMITS_MAGENTA) + (r - b) * basis(&SMITS_RED)
+            }
+    } else {
+        b * basis(&SMITS_WHITE)
+            + if r <= g {
+                (r - b) * basis(&SMITS_YELLOW) + (g - r) * basis(&SMITS_GREEN)
+            } else {
+                (g - b) * basis(&SMITS_YELLOW) + (r - g) * basis(&SMITS_RED)
+            }
+    }
+}
+
+// CIE 1931 color matching functions, multi-lobe Gaussian fit (Wyman et al. 2013)
+pub fn cie_xyz(wavelength: f64) -> (f64, f64, f64) {
+    let lobe = |mean: f64, sigma_below: f64, sigma_above: f64| {
+        let sigma = if wavelength < mean {
+            sigma_below
+        } else {
+            sigma_above
+        };
+        let t = (wavelength - mean) / sigma;
+        f64::exp(-0.5 * t * t)
+    };
+
+    (
+        1.056 * lobe(599.8, 37.9, 31.0) + 0.362 * lobe(442.0, 16.0, 26.7)
+            - 0.065 * lobe(501.1, 20.4, 26.2),
+        0.821 * lobe(568.8, 46.9, 40.5) + 0.286 * lobe(530.9, 16.3, 31.1),
+        1.217 * lobe(437.0, 11.8, 36.0) + 0.681 * lobe(459.0, 26.0, 13.8),
+    )
+}
+
+pub const CIE_Y_INTEGRAL: f64 = 106.856895;
+
+// Linear sRGB from XYZ, without any white balancing
+pub fn xyz_to_linear_srgb(x: f64, y: f64, z: f64) -> vec3::Color {
+    vec3::Color::new(
+        3.2404542 * x - 1.5371385 * y - 0.4985314 * z,
+        -0.9692660 * x + 1.8760108 * y + 0.0415560 * z,
+        0.0556434 * x - 0.2040259 * y + 1.0572252 * z,
+    )
+}
+
+// Linear sRGB of a spectrum, white balanced so a constant spectrum of 1 is (1, 1, 1)
+pub fn spectrum_to_rgb(spectrum: &dyn Spectrum) -> vec3::Color {
+    let (mut x, mut y, mut z) = (0.0, 0.0, 0.0);
+    let mut wavelength = LAMBDA_MIN;
+    while wavelength <= LAMBDA_MAX {
+        let value = spectrum.value(wavelength);
+        let (x_bar, y_bar, z_bar) = cie_xyz(wavelength);
+        x += x_bar * value;
+        y += y_bar * value;
+        z += z_bar * value;
+        wavelength += 1.0;
+    }
+
+    white_balance(xyz_to_linear_srgb(
+        x / CIE_Y_INTEGRAL,
+        y / CIE_Y_INTEGRAL,
+        z / CIE_Y_INTEGRAL,
+    ))
+}
+
+// Equal energy white to display white
+fn white_balance(rgb: vec3::Color) -> vec3::Color {
+    static WHITE: once_cell::sync::Lazy<vec3::Color> = once_cell::sync::Lazy::new(|| {
+        let (mut x, mut y, mut z) = (0.0, 0.0, 0.0);
+        let mut wavelength = LAMBDA_MIN;
+        while wavelength <= LAMBDA_MAX {
+            let (x_bar, y_bar, z_bar) = cie_xyz(wavelength);
+            x += x_bar;
+            y += y_bar;
+            z += z_bar;
+            wavelength += 1.0;
+        }
+        xyz_to_linear_srgb(x / CIE_Y_INTEGRAL, y / CIE_Y_INTEGRAL, z / CIE_Y_INTEGRAL)
+    });
+
+    vec3::Color::new(
+        rgb.x() / WHITE.x(),
+        rgb.y() / WHITE.y(),
+        rgb.z() / WHITE.z(),
+    )
+}
+
+// Wavelengths traced together by one spectral path, with the densities they
+// were sampled with. The first is the hero wavelength, which is the only one
+// left once a dispersive material separates the others.
+#[derive(Clone, Debug)]
+pub struct SampledWavelengths {
+    wavelengths: [f64; SPECTRUM_SAMPLES],
+    pdfs: [f64; SPECTRUM_SAMPLES],
+}
+
+impl SampledWavelengths {
+    // Stratified over the range, favoring wavelengths the eye is most sensitive to
+    pub fn sample_visible(sample: f64) -> Self {
+        let mut wavelengths = [0.0; SPECTRUM_SAMPLES];
+        let mut pdfs = [0.0; SPECTRUM_SAMPLES];
+        for i in 0..SPECTRUM_SAMPLES {
+            let offset = (sample + i as f64 / SPECTRUM_SAMPLES as f64).fract();
+            wavelengths[i] = sample_visible_wavelength(offset);
+            pdfs[i] = visible_wavelength_pdf(wavelengths[i]);
+        }
+        Self { wavelengths, pdfs }
+    }
+
+    pub fn hero(&self) -> f64 {
+        self.wavelengths[0]
+    }
+
+    pub fn wavelengths(&self) -> &[f64; SPECTRUM_SAMPLES] {
+        &self.wavelengths
+    }
+
+    pub fn secondary_terminated(&self) -> bool {
+        self.pdfs[1..].iter().all(|pdf| *pdf == 0.0)
+    }
+
+    // Keeps only the hero wavelength, which now stands for all of them
+    pub fn terminate_secondary(&mut self) {
+        if self.secondary_terminated() {
+            return;
+        }
+        self.pdfs[1..].fill(0.0);
+        self.pdfs[0] /= SPECTRUM_SAMPLES as f64;
+    }
+
+    // Monte Carlo estimate of the white balanced linear sRGB of `radiance`
+    pub fn to_rgb(&self, radiance: &SampledSpectrum) -> vec3::Color {
+        let (mut x, mut y, mut z) = (0.0, 0.0, 0.0);
+        for i in 0..SPECTRUM_SAMPLES {
+            if self.pdfs[i] == 0.0 {
+                continue;
+            }
+            let (x_bar, y_bar, z_bar) = cie_xyz(self.wavelengths[i]);
+            let weight = radiance.values[i] / self.pdfs[i];
+            x += x_bar * weight;
+            y += y_bar * weight;
+            z += z_bar * weight;
+        }
+        let scale = 1.0 / (SPECTRUM_SAMPLES as f64 * CIE_Y_INTEGRAL);
+
+        white_balance(xyz_to_linear_srgb(x * scale, y * scale, z * scale))
+    }
+}
+
+// Approximation of the luminous efficiency curve that's cheap to sample (Radziszewski 2009)
+fn sample_visible_wavelength(sample: f64) -> f64 {
+    538.0 - 138.888889 * f64::atanh(0.85691062 - 1.82750197 * sample)
+}
+
+fn visible_wavelength_pdf(wavelength: f64) -> f64 {
+    if !(LAMBDA_MIN..=LAMBDA_MAX).contains(&wavelength) {
+        return 0.0;
+    }
+    let cosh = f64::cosh(0.0072 * (wavelength - 538.0));
+    0.0039398042 / (cosh * cosh)
+}
+
+// Values of a spectrum at the wavelengths of a path
+#[derive(Clone, Debug)]
+pub struct SampledSpectrum {
+    values: [f64; SPECTRUM_SAMPLES],
+}
+
+impl SampledSpectrum {
+    pub fn new(values: [f64; SPECTRUM_SAMPLES]) -> Self {
+        Self { values }
+    }
+
+    pub fn constant(value: f64) -> Self {
+        Self::new([value; SPECTRUM_SAMPLES])
+    }
+
+    pub fn from_rgb(rgb: &vec3::Color, wavelengths: &SampledWavelengths) -> Self {
+        RgbSpectrum::new(rgb.clone()).sample(wavelengths)
+    }
+
+    pub fn max_component(&self) -> f64 {
+        self.values.iter().copied().fold(f64::MIN, f64::max)
+    }
+}
+
+impl std::ops::Add for SampledSpectrum {
+    type Output = Self;
+    fn add(mut self, rhs: Self) -> Self::Output {
+        self += rhs;
+        self
+    }
+}
+impl std::ops::AddAssign for SampledSpectrum {
+    fn add_assign(&mut self, rhs: Self) {
+        for (value, other) in self.values.iter_mut().zip(rhs.values) {
+            *value += other;
+        }
+    }
+}
+impl std::ops::Mul for SampledSpectrum {
+    type Output = Self;
+    fn mul(mut self, rhs: Self) -> Self::Output {
+        for (value, other) in self.values.iter_mut().zip(rhs.values) {
+            *value *= other;
+        }
+        self
+    }
+}
+impl std::ops::Mul<f64> for SampledSpectrum {
+    type Output = Self;
+    fn mul(mut self, rhs: f64) -> Self::Output {
+        self *= rhs;
+        self
+    }
+}
+impl std::ops::MulAssign<f64> for SampledSpectrum {
+    fn mul_assign(&mut self, rhs: f64) {
+        for value in &mut self.values {
+            *value *= rhs;
+        }
+    }
+}
+impl std::ops::DivAssign<f64> for SampledSpectrum {
+    fn div_assign(&mut self, rhs: f64) {
+        *self *= 1.0 / rhs;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: &vec3::Color, expected: &vec3::Color, tolerance: f64) {
+        for channel in 0..3 {
+            assert!(
+                (actual[channel] - expected[channel]).abs() < tolerance,
+                "{actual} isn't within {tolerance} of {expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn constant_spectrum_is_white() {
+        let rgb = spectrum_to_rgb(&ConstantSpectrum::new(1.0));
+        assert_close(&rgb, &vec3::Color::new(1.0, 1.0, 1.0), 1e-9);
+    }
+
+    #[test]
+    fn primaries_round_trip() {
+        for rgb in [
+            vec3::Color::new(1.0, 0.0, 0.0),
+            vec3::Color::new(0.0, 1.0, 0.0),
+            vec3::Color::new(0.0, 0.0, 1.0),
+            vec3::Color::new(1.0, 1.0, 1.0),
+        ] {
+            let round_trip = spectrum_to_rgb(&RgbSpectrum::new(rgb.clone()));
+            assert_close(&round_trip, &rgb, 0.05);
+        }
+    }
+
+    #[test]
+    fn tabulated_spectrum_interpolates_samples() {
+        let spectrum = TabulatedSpectrum::new(vec![400.0, 500.0, 700.0], vec![1.0, 3.0, 2.0]);
+        assert_eq!(spectrum.value(400.0), 1.0);
+        assert_eq!(spectrum.value(500.0), 3.0);
+        assert_eq!(spectrum.value(700.0), 2.0);
+        assert!((spectrum.value(450.0) - 2.0).abs() < 1e-12);
+        assert!((spectrum.value(600.0) - 2.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn tabulated_spectrum_is_zero_outside_its_samples() {
+        let spectrum = TabulatedSpectrum::new(vec![400.0, 500.0], vec![1.0, 3.0]);
+        assert_eq!(spectrum.value(399.9), 0.0);
+        assert_eq!(spectrum.value(500.1), 0.0);
+        assert_eq!(
+            TabulatedSpectrum::new(Vec::new(), Vec::new()).value(550.0),
+            0.0
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Black body temperature must be positive")]
+    fn blackbody_rejects_zero_kelvin() {
+        BlackbodySpectrum::new(0.0);
+    }
+}
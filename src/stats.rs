@@ -0,0 +1,94 @@
+// Opt-in counters of what the renderer does. Collection is per thread and
+// costs next to nothing while disabled.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+thread_local! {
+    static STATISTICS: RefCell<Option<Statistics>> = const { RefCell::new(None) };
+}
+
+// Why a path stopped bouncing
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Termination {
+    MaxBounces,
+    // The material didn't scatter the ray, like lights do
+    Absorbed,
+    Escaped,
+    RussianRoulette,
+    // The integrator shaded the hit directly instead of bouncing on, like
+    // Whitted on diffuse surfaces or the normals, depth and AO integrators
+    Shaded,
+}
+
+#[derive(Clone, Default, Debug)]
+pub struct Statistics {
+    pub primary_rays: u64,
+    // Every ray intersected with the scene, primary rays included
+    pub rays_traced: u64,
+    // `Hittable::hit` calls on the objects of the scene
+    pub intersection_tests: u64,
+    // Calls to `Material::scatter` by material name
+    pub scatters: BTreeMap<&'static str, u64>,
+    pub paths: u64,
+    pub path_segments: u64,
+    pub terminations: BTreeMap<Termination, u64>,
+}
+
+impl Statistics {
+    pub fn secondary_rays(&self) -> u64 {
+        self.rays_traced.saturating_sub(self.primary_rays)
+    }
+
+    pub fn average_path_length(&self) -> f64 {
+        if self.paths == 0 {
+            return 0.0;
+        }
+        self.path_segments as f64 / self.paths as f64
+    }
+
+    pub(crate) fn end_path(&mut self, segments: u32, termination: Termination) {
+        self.paths += 1;
+        self.path_segments += segments as u64;
+        *self.terminations.entry(termination).or_default() += 1;
+    }
+}
+
+impl std::fmt::Display for Statistics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Primary rays: {}", self.primary_rays)?;
+        writeln!(f, "Secondary rays: {}", self.secondary_rays())?;
+        writeln!(f, "Intersection tests: {}", self.intersection_tests)?;
+        for (material, count) in &self.scatters {
+            writeln!(f, "Scatters off {material}: {count}")?;
+        }
+        writeln!(f, "Average path length: {:.2}", self.average_path_length())?;
+        for (termination, count) in &self.terminations {
+            let share = *count as f64 / self.paths.max(1) as f64;
+            writeln!(
+                f,
+                "Paths ended by {termination:?}: {count} ({:.1}%)",
+                100.0 * share
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// Starts collecting from zero on this thread
+pub fn enable() {
+    STATISTICS.with(|statistics| *statistics.borrow_mut() = Some(Statistics::default()));
+}
+
+// Stops collecting, returning what was collected if collection was enabled
+pub fn take() -> Option<Statistics> {
+    STATISTICS.with(|statistics| statistics.borrow_mut().take())
+}
+
+pub(crate) fn record(update: impl FnOnce(&mut Statistics)) {
+    STATISTICS.with(|statistics| {
+        if let Some(statistics) = statistics.borrow_mut().as_mut() {
+            update(statistics);
+        }
+    });
+}
@@ -0,0 +1,59 @@
+use crate::vec3;
+use std::rc::Rc;
+
+// Color varying over the surface of an object, looked up by world position
+pub trait Texture {
+    fn value(&self, point: &vec3::Point3) -> vec3::Color;
+}
+
+pub struct SolidColor {
+    albedo: vec3::Color,
+}
+
+impl SolidColor {
+    pub fn new(albedo: vec3::Color) -> Self {
+        Self { albedo }
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _point: &vec3::Point3) -> vec3::Color {
+        self.albedo.clone()
+    }
+}
+
+// 3D checkerboard of cubes with sides of `scale`, alternating two textures
+pub struct CheckerTexture {
+    inverse_scale: f64,
+    even: Rc<dyn Texture>,
+    odd: Rc<dyn Texture>,
+}
+
+impl CheckerTexture {
+    pub fn new(scale: f64, even: Rc<dyn Texture>, odd: Rc<dyn Texture>) -> Self {
+        Self {
+            inverse_scale: 1.0 / scale,
+            even,
+            odd,
+        }
+    }
+
+    pub fn from_colors(scale: f64, even: vec3::Color, odd: vec3::Color) -> Self {
+        Self::new(
+            scale,
+            Rc::new(SolidColor::new(even)),
+            Rc::new(SolidColor::new(odd)),
+        )
+    }
+}
+
+impl Texture for CheckerTexture {
+    fn value(&self, point: &vec3::Point3) -> vec3::Color {
+        let cell = |coordinate: f64| f64::floor(self.inverse_scale * coordinate) as i64;
+        if (cell(point.x()) + cell(point.y()) + cell(point.z())) % 2 == 0 {
+            self.even.value(point)
+        } else {
+            self.odd.value(point)
+        }
+    }
+}
@@ -0,0 +1,122 @@
+// Rectangle of pixels rendered as one unit of work, end exclusive
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Tile {
+    pub row_start: usize,
+    pub row_end: usize,
+    pub column_start: usize,
+    pub column_end: usize,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TileOrder {
+    // Left to right, top to bottom
+    Scanline,
+    // Rings of tiles around the center tile, working outwards
+    Spiral,
+    // Along a Hilbert curve, keeping consecutive tiles next to each other
+    Hilbert,
+}
+
+// Region of the image to render, either in pixels or as fractions of the
+// image size. Both are end exclusive.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CropWindow {
+    Pixels(Tile),
+    Normalized {
+        x_min: f64,
+        x_max: f64,
+        y_min: f64,
+        y_max: f64,
+    },
+}
+
+impl CropWindow {
+    // The window clamped to a `width` x `height` image
+    pub fn to_pixels(&self, width: usize, height: usize) -> Tile {
+        let tile = match self {
+            Self::Pixels(tile) => tile.clone(),
+            Self::Normalized {
+                x_min,
+                x_max,
+                y_min,
+                y_max,
+            } => Tile {
+                row_start: (y_min * height as f64).floor() as usize,
+                row_end: (y_max * height as f64).ceil() as usize,
+                column_start: (x_min * width as f64).floor() as usize,
+                column_end: (x_max * width as f64).ceil() as usize,
+            },
+        };
+
+        let row_end = usize::min(tile.row_end, height);
+        let column_end = usize::min(tile.column_end, width);
+        Tile {
+            row_start: usize::min(tile.row_start, row_end),
+            row_end,
+            column_start: usize::min(tile.column_start, column_end),
+            column_end,
+        }
+    }
+}
+
+// Splits `region` into tiles of at most `tile_size` pixels a side, in the given order
+pub fn tiles(region: &Tile, tile_size: usize, order: TileOrder) -> Vec<Tile> {
+    debug_assert!(tile_size >= 1, "Tiles must be at least one pixel wide");
+    let tiles_x = (region.column_end - region.column_start).div_ceil(tile_size);
+    let tiles_y = (region.row_end - region.row_start).div_ceil(tile_size);
+
+    let mut coordinates: Vec<(usize, usize)> = (0..tiles_y)
+        .flat_map(|y| (0..tiles_x).map(move |x| (x, y)))
+        .collect();
+
+    match order {
+        TileOrder::Scanline => {}
+        TileOrder::Spiral => {
+            let center = ((tiles_x as f64 - 1.0) / 2.0, (tiles_y as f64 - 1.0) / 2.0);
+            let key = |(x, y): &(usize, usize)| {
+                let dx = *x as f64 - center.0;
+                let dy = *y as f64 - center.1;
+                let ring = f64::max(f64::abs(dx), f64::abs(dy)).round();
+                (ring, f64::atan2(dy, dx))
+            };
+            coordinates.sort_by(|a, b| key(a).partial_cmp(&key(b)).unwrap());
+        }
+        TileOrder::Hilbert => {
+            let side = usize::max(tiles_x, tiles_y).next_power_of_two();
+            coordinates.sort_by_key(|(x, y)| hilbert_index(side, *x, *y));
+        }
+    }
+
+    coordinates
+        .into_iter()
+        .map(|(x, y)| Tile {
+            row_start: region.row_start + y * tile_size,
+            row_end: usize::min(region.row_start + (y + 1) * tile_size, region.row_end),
+            column_start: region.column_start + x * tile_size,
+            column_end: usize::min(region.column_start + (x + 1) * tile_size, region.column_end),
+        })
+        .collect()
+}
+
+// Distance along the Hilbert curve filling a `side` x `side` grid, `side` a power of two
+fn hilbert_index(side: usize, mut x: usize, mut y: usize) -> usize {
+    let mut index = 0;
+    let mut s = side / 2;
+    while s > 0 {
+        let rx = usize::from(x & s > 0);
+        let ry = usize::from(y & s > 0);
+        index += s * s * ((3 * rx) ^ ry);
+
+        // Rotate the quadrant so the curve stays continuous
+        if ry == 0 {
+            if rx == 1 {
+                x = side - 1 - x;
+                y = side - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+
+    index
+}
@@ -62,6 +62,39 @@ impl Vec3 {
         }
     }
 
+    // Uniform direction on the unit sphere from a point of the unit square
+    pub fn sample_unit_vector(sample: (f64, f64)) -> Self {
+        let z = 1.0 - 2.0 * sample.0;
+        let radius = f64::sqrt(f64::max(0.0, 1.0 - z * z));
+        let phi = 2.0 * consts::PI * sample.1;
+
+        Self::new(radius * f64::cos(phi), radius * f64::sin(phi), z)
+    }
+
+    // Cosine weighted direction around the +z axis
+    pub fn sample_cosine_direction(sample: (f64, f64)) -> Self {
+        let (r1, r2) = sample;
+
+        let phi = 2.0 * consts::PI * r1;
+        let x = f64::cos(phi) * f64::sqrt(r2);
+        let y = f64::sin(phi) * f64::sqrt(r2);
+        let z = f64::sqrt(1.0 - r2);
+
+        Self::new(x, y, z)
+    }
+
+    // Direction around the +z axis towards a sphere of `radius` at `distance_squared`
+    pub fn sample_to_sphere(radius: f64, distance_squared: f64, sample: (f64, f64)) -> Self {
+        let (r1, r2) = sample;
+        let z = 1.0 + r2 * (f64::sqrt(1.0 - radius * radius / distance_squared) - 1.0);
+
+        let phi = 2.0 * consts::PI * r1;
+        let x = f64::cos(phi) * f64::sqrt(1.0 - z * z);
+        let y = f64::sin(phi) * f64::sqrt(1.0 - z * z);
+
+        Self::new(x, y, z)
+    }
+
     pub const fn zeroed() -> Self {
         Self {
             points: [0.0, 0.0, 0.0],
@@ -88,6 +121,14 @@ impl Vec3 {
             self.x() * other.y() - self.y() * other.x(),
         )
     }
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.x() + 0.7152 * self.y() + 0.0722 * self.z()
+    }
+
+    pub fn max_component(&self) -> f64 {
+        f64::max(self.x(), f64::max(self.y(), self.z()))
+    }
+
     pub fn near_zero(&self) -> bool {
         let diff = 1e-8;
 
@@ -111,11 +152,7 @@ impl Vec3 {
         self / self.length()
     }
 
-    pub fn write_color(
-        &self,
-        buffer: &mut std::io::BufWriter<std::io::Stdout>,
-        samples_per_pixel: f64,
-    ) {
+    pub fn write_color(&self, buffer: &mut impl std::io::Write, samples_per_pixel: f64) {
         use super::linear_space_to_gamma_space;
 
         let mut r = self.x();
@@ -132,7 +169,6 @@ impl Vec3 {
         b = linear_space_to_gamma_space(b);
 
         let intensity = consts::Interval::new(0.0, 0.999);
-        use std::io::Write;
         write!(
             buffer,
             "{} {} {} ",